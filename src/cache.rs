@@ -0,0 +1,293 @@
+use crate::capacity::{capacity_with, CapacityBackend};
+use crate::error::{Error, Result};
+use crate::journal::payload_hash;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/**
+ * Name of the cache file. The version is bumped whenever the way capacity is computed changes,
+ * so that stale capacities are never read back.
+ */
+const CACHE_FILE_NAME: &str = "capacity-v2";
+
+fn backend_name(backend: CapacityBackend) -> &'static str {
+    match backend {
+        CapacityBackend::Steghide => "steghide",
+        CapacityBackend::Native => "native",
+    }
+}
+
+/**
+ * Most capacities kept in the cache. Once there are more, the ones that went unused for the most
+ * runs are dropped. At about 90 bytes a line, this keeps the file to a few megabytes.
+ */
+const MAX_ENTRIES: usize = 50_000;
+
+/**
+ * A cached capacity, and the last run that used it. Runs are numbered in the order they saved
+ * the cache.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    capacity: u64,
+    run: u64,
+}
+
+/**
+ * Capacities found so far, keyed by the SHA-256 of each image and the backend that found it.
+ * An image that changes gets a new hash, so its old capacity is simply never looked up again,
+ * and is eventually dropped as the cache is kept to its most recently used entries. The file is
+ * line based and tab separated:
+ *
+ * <sha256 of the image> <backend> <capacity> <last run that used it>
+ */
+pub struct CapacityCache {
+    path: Option<PathBuf>,
+    entries: Mutex<HashMap<(String, String), Entry>>,
+    /// Number of this run, one past the last run in the cache.
+    run: u64,
+    max_entries: usize,
+    changed: Mutex<bool>,
+}
+
+impl CapacityCache {
+    /**
+     * Open the cache in `dir`. A missing or unreadable cache starts out empty.
+     */
+    pub fn open(dir: &Path) -> CapacityCache {
+        CapacityCache::open_with_limit(dir, MAX_ENTRIES)
+    }
+
+    fn open_with_limit(dir: &Path, max_entries: usize) -> CapacityCache {
+        let path = dir.join(CACHE_FILE_NAME);
+        let contents = fs::read_to_string(&path).unwrap_or_default();
+
+        let entries: HashMap<(String, String), Entry> = contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let hash = fields.next()?.to_string();
+                let backend = fields.next()?.to_string();
+                let capacity = fields.next()?.parse().ok()?;
+                // Caches written before runs were recorded count as the oldest run
+                let run = fields.next().map_or(Some(0), |run| run.parse().ok())?;
+                Some(((hash, backend), Entry { capacity, run }))
+            })
+            .collect();
+        let run = entries
+            .values()
+            .map(|entry| entry.run + 1)
+            .max()
+            .unwrap_or(1);
+
+        CapacityCache {
+            path: Some(path),
+            entries: Mutex::new(entries),
+            run,
+            max_entries,
+            changed: Mutex::new(false),
+        }
+    }
+
+    /**
+     * Open the cache in the user's cache directory. If there is none, capacities are only
+     * remembered until the cache is dropped.
+     */
+    pub fn open_default() -> CapacityCache {
+        match dirs::cache_dir() {
+            Some(dir) => CapacityCache::open(&dir.join("stegfile")),
+            None => CapacityCache {
+                path: None,
+                entries: Mutex::new(HashMap::new()),
+                run: 1,
+                max_entries: MAX_ENTRIES,
+                changed: Mutex::new(false),
+            },
+        }
+    }
+
+    /**
+     * Capacity of `photo_path` found with `backend`, from the cache if the image has been seen
+     * before. Several images may be looked up at once.
+     */
+    pub fn capacity(&self, photo_path: &str, backend: CapacityBackend) -> Result<u64> {
+        self.capacity_or_else(photo_path, backend, capacity_with)
+    }
+
+    fn capacity_or_else(
+        &self,
+        photo_path: &str,
+        backend: CapacityBackend,
+        compute: impl FnOnce(&str, CapacityBackend) -> Result<u64>,
+    ) -> Result<u64> {
+        // An image that can't be read can't be cached either
+        let hash = match fs::read(photo_path) {
+            Ok(contents) => payload_hash(&contents),
+            Err(_) => return compute(photo_path, backend),
+        };
+
+        let key = (hash, backend_name(backend).to_string());
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&key) {
+            debug!(
+                "capacity of {} is cached: {} bytes",
+                photo_path, entry.capacity
+            );
+            if entry.run != self.run {
+                entry.run = self.run;
+                *self.changed.lock().unwrap() = true;
+            }
+            return Ok(entry.capacity);
+        }
+
+        // The lock is not held while computing, so other images can be looked up meanwhile
+        let capacity = compute(photo_path, backend)?;
+        let entry = Entry {
+            capacity,
+            run: self.run,
+        };
+        self.entries.lock().unwrap().insert(key, entry);
+        *self.changed.lock().unwrap() = true;
+        Ok(capacity)
+    }
+
+    /**
+     * Write the cache back if anything was added to it or used from it, dropping the entries
+     * that went unused for the most runs once there are too many. The new cache replaces the old
+     * one in one rename, so a cache is never left half written.
+     */
+    pub fn save(&self) -> Result<()> {
+        let mut changed = self.changed.lock().unwrap();
+        let path = match &self.path {
+            Some(path) if *changed => path,
+            _ => return Ok(()),
+        };
+
+        let entries = self.entries.lock().unwrap();
+        let mut kept: Vec<(&(String, String), &Entry)> = entries.iter().collect();
+        kept.sort_by(|a, b| b.1.run.cmp(&a.1.run).then(a.0.cmp(b.0)));
+        kept.truncate(self.max_entries);
+
+        let mut contents = String::new();
+        for ((hash, backend), entry) in kept {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                hash, backend, entry.capacity, entry.run
+            ));
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        let partial = path.with_extension("partial");
+        fs::write(&partial, contents).map_err(|e| Error::io(&partial, e))?;
+        fs::rename(&partial, path).map_err(|e| Error::io(path, e))?;
+        debug!("saved capacity cache to {}", path.display());
+
+        *changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_by_content() {
+        let dir = TempDir::new().unwrap();
+        let image = dir.path().join("a.jpg");
+        let image_path = image.to_str().unwrap();
+        fs::write(&image, b"first").unwrap();
+
+        let computed = Cell::new(0);
+        let compute = |_: &str, _: CapacityBackend| {
+            computed.set(computed.get() + 1);
+            Ok(1000 * computed.get())
+        };
+
+        let cache = CapacityCache::open(dir.path());
+        assert_eq!(
+            cache.capacity_or_else(image_path, CapacityBackend::Native, compute),
+            Ok(1000)
+        );
+        cache.save().unwrap();
+
+        // Found again after reopening, but not for another backend
+        let cache = CapacityCache::open(dir.path());
+        assert_eq!(
+            cache.capacity_or_else(image_path, CapacityBackend::Native, compute),
+            Ok(1000)
+        );
+        assert_eq!(
+            cache.capacity_or_else(image_path, CapacityBackend::Steghide, compute),
+            Ok(2000)
+        );
+
+        // Changing the image invalidates its capacity
+        fs::write(&image, b"second").unwrap();
+        assert_eq!(
+            cache.capacity_or_else(image_path, CapacityBackend::Native, compute),
+            Ok(3000)
+        );
+
+        // Failures are not cached
+        fs::write(&image, b"third").unwrap();
+        let fail = |_: &str, _: CapacityBackend| Err(Error::NoImages);
+        assert!(cache
+            .capacity_or_else(image_path, CapacityBackend::Native, fail)
+            .is_err());
+        assert_eq!(
+            cache.capacity_or_else(image_path, CapacityBackend::Native, compute),
+            Ok(4000)
+        );
+        assert_eq!(computed.get(), 4);
+    }
+
+    #[test]
+    fn test_cache_keeps_every_library() {
+        let dir = TempDir::new().unwrap();
+        let library = |name: &str| {
+            let image = dir.path().join(name);
+            fs::write(&image, name.as_bytes()).unwrap();
+            image.to_str().unwrap().to_string()
+        };
+        let (a, b, c) = (library("a.jpg"), library("b.jpg"), library("c.jpg"));
+
+        let computed = Cell::new(0);
+        let compute = |_: &str, _: CapacityBackend| {
+            computed.set(computed.get() + 1);
+            Ok(1000 * computed.get())
+        };
+        let run = |images: &[&String]| {
+            let cache = CapacityCache::open_with_limit(dir.path(), 2);
+            let capacities: Vec<u64> = images
+                .iter()
+                .map(|image| {
+                    cache
+                        .capacity_or_else(image, CapacityBackend::Native, compute)
+                        .unwrap()
+                })
+                .collect();
+            cache.save().unwrap();
+            capacities
+        };
+
+        // Embedding into one library and then another keeps the capacities of both
+        assert_eq!(run(&[&a]), vec!(1000));
+        assert_eq!(run(&[&b]), vec!(2000));
+        assert_eq!(run(&[&a, &b]), vec!(1000, 2000));
+        assert_eq!(computed.get(), 2);
+
+        // Past the limit, the capacity that went unused for the most runs is dropped
+        assert_eq!(run(&[&b]), vec!(2000));
+        assert_eq!(run(&[&c]), vec!(3000));
+        assert_eq!(run(&[&b, &c]), vec!(2000, 3000));
+        assert_eq!(run(&[&a]), vec!(4000));
+        assert_eq!(computed.get(), 4);
+    }
+}
@@ -0,0 +1,439 @@
+use crate::cache::CapacityCache;
+use crate::error::{Error, Result};
+use crate::events::{Event, Observer};
+use crate::jpeg;
+use crate::piece::HEADER_SIZE;
+use crate::placement::capacity_at_rate;
+use crate::pool::{collect_results, parallel_map};
+use crate::split::{Split, SplitChunks, SplitScrambled};
+use crate::steghide;
+use log::debug;
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/**
+ * Steghide really likes to write its own stuff to the file, so this many bytes are removed from
+ * the capacity of every file just to really make sure we don't write to it.
+ */
+const STEGHIDE_MARGIN: u64 = 100;
+
+/**
+ * How the capacity of an image is found.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityBackend {
+    /// Ask `steghide --info`, one process per image.
+    Steghide,
+    /// Count the coefficients of the JPEG directly, the way steghide does. Images that can't be
+    /// read this way, such as progressive JPEGs, are handed to steghide instead.
+    Native,
+}
+
+/**
+ * Read the capacity in bytes out of what `steghide --info` printed for `image`. Steghide prints
+ * capacities in units of 1024 rounded to one decimal, so up to half of that last decimal is
+ * taken off to never report more than there is.
+ */
+fn parse_info(image: &str, stdout: &str, stderr: &str) -> Result<u64> {
+    if stderr.contains("format of the file") && stderr.contains("not supported") {
+        return Err(Error::UnsupportedFormat {
+            image: image.to_string(),
+        });
+    }
+
+    // Only the line that starts with the label, as the file name is printed too
+    let capacity_value = match stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("capacity:"))
+    {
+        Some(value) => value.trim(),
+        None => {
+            return Err(Error::NoCapacity {
+                image: image.to_string(),
+                message: stderr
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        }
+    };
+
+    let unreadable = || Error::UnreadableCapacity {
+        image: image.to_string(),
+        value: capacity_value.to_string(),
+    };
+
+    let mut parts = capacity_value.split_whitespace();
+    let value: f64 = parts
+        .next()
+        .and_then(|value| value.parse().ok())
+        .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+        .ok_or_else(unreadable)?;
+
+    let (multiplier, rounding) = match parts.next() {
+        Some("Byte" | "Bytes") => (1.0, 0.0),
+        Some("KB") => (1024.0, 0.05),
+        Some("MB") => (1024.0 * 1024.0, 0.05),
+        Some("GB") => (1024.0 * 1024.0 * 1024.0, 0.05),
+        Some("TB") => (1024.0 * 1024.0 * 1024.0 * 1024.0, 0.05),
+        _ => return Err(unreadable()),
+    };
+    if parts.next().is_some() {
+        return Err(unreadable());
+    }
+
+    Ok(((value - rounding).max(0.0) * multiplier) as u64)
+}
+
+/**
+ * Capacity of `photo_path` as reported by `steghide --info`, less a safety margin.
+ */
+pub fn one_file_capacity(photo_path: &str) -> Result<u64> {
+    let mut command = Command::new("steghide");
+    command
+        .arg("--info")
+        .arg(photo_path)
+        // Steghide asks whether to look for embedded data; nothing answers, so it doesn't
+        .stdin(Stdio::null());
+    let output = steghide::output(&mut command, photo_path)?;
+
+    let capacity = parse_info(
+        photo_path,
+        &String::from_utf8_lossy(&output.stdout),
+        &String::from_utf8_lossy(&output.stderr),
+    )?;
+    Ok(capacity.saturating_sub(STEGHIDE_MARGIN))
+}
+
+/**
+ * Capacity of `photo_path` found with `backend`.
+ */
+pub fn capacity_with(photo_path: &str, backend: CapacityBackend) -> Result<u64> {
+    match backend {
+        CapacityBackend::Steghide => one_file_capacity(photo_path),
+        CapacityBackend::Native => match jpeg::steghide_capacity(Path::new(photo_path)) {
+            Some(capacity) => Ok(capacity.saturating_sub(STEGHIDE_MARGIN)),
+            None => {
+                debug!("{} can't be read natively, asking steghide", photo_path);
+                one_file_capacity(photo_path)
+            }
+        },
+    }
+}
+
+/**
+ * Space left in `photo_path` for piece data once the piece header has been written, looked up
+ * in `cache` first.
+ */
+pub fn usable_capacity(
+    cache: &CapacityCache,
+    photo_path: &str,
+    backend: CapacityBackend,
+) -> Result<u64> {
+    let capacity = cache.capacity(photo_path, backend)?;
+    Ok(capacity.saturating_sub(HEADER_SIZE as u64))
+}
+
+/**
+ * `usable_capacity` of every image in `files`, in order, found `jobs` images at a time. The
+ * cache is saved afterwards. Every image that fails is reported.
+ */
+pub fn usable_capacities(
+    files: &[String],
+    backend: CapacityBackend,
+    jobs: usize,
+    observer: &dyn Observer,
+) -> Result<Vec<u64>> {
+    observer.event(&Event::CapacityStarted {
+        images: files.len(),
+    });
+
+    let cache = CapacityCache::open_default();
+    let results = parallel_map(files, jobs, |file| {
+        let result = usable_capacity(&cache, file, backend);
+        observer.event(&match &result {
+            Ok(capacity) => Event::CapacityFound {
+                image: file.clone(),
+                capacity: *capacity,
+            },
+            Err(error) => Event::CapacityFailed {
+                image: file.clone(),
+                error: error.clone(),
+            },
+        });
+        result
+    });
+
+    // The cache only saves time, so failing to write it is not worth failing over
+    if let Err(error) = cache.save() {
+        observer.event(&Event::Warning {
+            message: format!("could not save capacity cache: {}", error),
+        });
+    }
+    observer.event(&Event::CapacityFinished);
+    collect_results(results)
+}
+
+fn capacities_at_rate(
+    files: &[String],
+    max_rate: u8,
+    backend: CapacityBackend,
+    jobs: usize,
+    observer: &dyn Observer,
+) -> Result<Vec<u64>> {
+    let capacities = usable_capacities(files, backend, jobs, observer)?;
+    Ok(capacities
+        .into_iter()
+        .map(|capacity| capacity_at_rate(capacity, max_rate))
+        .collect())
+}
+
+/**
+ * Capacity of one image, in bytes.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImageCapacity {
+    pub image: String,
+    pub capacity: u64,
+}
+
+/**
+ * Capacity of a set of images with one split method. `images` lists how much of `total` each
+ * image would hold.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SplitCapacity {
+    pub split: String,
+    pub total: u64,
+    pub images: Vec<ImageCapacity>,
+}
+
+/**
+ * Capacity of a set of images with every split method. `images` holds the usable capacity of
+ * every image at `max_rate`, once room has been left for the piece header. As JSON:
+ *
+ * ```json
+ * {
+ *   "max_rate": 100,
+ *   "images": [{"image": "a.jpg", "capacity": 4000}, {"image": "b.jpg", "capacity": 9000}],
+ *   "splits": [
+ *     {"split": "scrambled", "total": 8000, "images": [{"image": "a.jpg", "capacity": 4000}, ...]},
+ *     {"split": "full", "total": 13000, "images": [{"image": "a.jpg", "capacity": 4000}, ...]}
+ *   ]
+ * }
+ * ```
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CapacityReport {
+    pub max_rate: u8,
+    pub images: Vec<ImageCapacity>,
+    pub splits: Vec<SplitCapacity>,
+}
+
+impl CapacityReport {
+    /**
+     * Total capacity with the split method `T`.
+     */
+    pub fn total<T: Split>(&self) -> u64 {
+        self.splits
+            .iter()
+            .find(|split| split.split == T::NAME)
+            .map_or(0, |split| split.total)
+    }
+}
+
+fn split_capacity<T: Split>(files: &[String], capacities: &[u64]) -> SplitCapacity {
+    SplitCapacity {
+        split: T::NAME.to_string(),
+        total: T::max_payload(capacities),
+        images: image_capacities(files, &T::max_bins(capacities)),
+    }
+}
+
+fn image_capacities(files: &[String], capacities: &[u64]) -> Vec<ImageCapacity> {
+    files
+        .iter()
+        .zip(capacities)
+        .map(|(image, &capacity)| ImageCapacity {
+            image: image.clone(),
+            capacity,
+        })
+        .collect()
+}
+
+/**
+ * Capacity of `files` with every split method, filling no file past `max_rate` percent of its
+ * capacity as found with `backend` for `jobs` files at a time. Progress is reported to
+ * `observer`.
+ */
+pub fn capacity_report(
+    files: &[String],
+    max_rate: u8,
+    backend: CapacityBackend,
+    jobs: usize,
+    observer: &dyn Observer,
+) -> Result<CapacityReport> {
+    let capacities = capacities_at_rate(files, max_rate, backend, jobs, observer)?;
+    Ok(CapacityReport {
+        max_rate,
+        images: image_capacities(files, &capacities),
+        splits: vec![
+            split_capacity::<SplitScrambled>(files, &capacities),
+            split_capacity::<SplitChunks>(files, &capacities),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /**
+     * Capacity of `size` bytes the way `steghide --info` prints it, which is always with one
+     * decimal.
+     */
+    fn steghide_format(size: u64) -> String {
+        match size {
+            0..1024 => format!("{:.1} Byte", size as f64),
+            1024..1_048_576 => format!("{:.1} KB", size as f64 / 1024.0),
+            _ => format!("{:.1} MB", size as f64 / 1_048_576.0),
+        }
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = |capacity: &str| {
+            format!(
+                "\"capacity.jpg\":\n  format: jpeg\n  capacity: {}\nTry to get information about embedded data ? (y/n) ",
+                capacity
+            )
+        };
+
+        assert_eq!(parse_info("a.jpg", &info("87.0 Byte"), ""), Ok(87));
+        assert_eq!(parse_info("a.jpg", &info("2.0 KB"), ""), Ok(1996));
+        assert_eq!(
+            parse_info("a.jpg", &info("1.5 MB"), ""),
+            Ok((1.45 * 1024.0 * 1024.0) as u64)
+        );
+        assert_eq!(parse_info("a.jpg", &info("0.0 KB"), ""), Ok(0));
+        assert_eq!(
+            parse_info("a.jpg", &info("1.0 TB"), ""),
+            Ok((0.95 * 1024.0 * 1024.0 * 1024.0 * 1024.0) as u64)
+        );
+
+        for bad in ["", "12", "-3 Byte", "1.2 PB", "abc KB", "1.0 KB extra"] {
+            assert_eq!(
+                parse_info("a.jpg", &info(bad), ""),
+                Err(Error::UnreadableCapacity {
+                    image: "a.jpg".to_string(),
+                    value: bad.to_string()
+                })
+            );
+        }
+
+        assert_eq!(
+            parse_info(
+                "a.png",
+                "",
+                "steghide: the file format of the file \"a.png\" is not supported.\n"
+            ),
+            Err(Error::UnsupportedFormat {
+                image: "a.png".to_string()
+            })
+        );
+        assert_eq!(
+            parse_info(
+                "a.jpg",
+                "\"a.jpg\":\n",
+                "\nsteghide: could not open the file \"a.jpg\".\n"
+            ),
+            Err(Error::NoCapacity {
+                image: "a.jpg".to_string(),
+                message: "steghide: could not open the file \"a.jpg\".".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_split_capacity() {
+        let files = vec!["a.jpg".to_string(), "b.jpg".to_string()];
+        let scrambled = split_capacity::<SplitScrambled>(&files, &[4000, 9000]);
+        assert_eq!(scrambled.total, 8000);
+        assert_eq!(
+            serde_json::to_string(&scrambled.images).unwrap(),
+            r#"[{"image":"a.jpg","capacity":4000},{"image":"b.jpg","capacity":4000}]"#
+        );
+
+        let report = CapacityReport {
+            max_rate: 100,
+            images: image_capacities(&files, &[4000, 9000]),
+            splits: vec![
+                scrambled,
+                split_capacity::<SplitChunks>(&files, &[4000, 9000]),
+            ],
+        };
+        assert_eq!(report.total::<SplitScrambled>(), 8000);
+        assert_eq!(report.total::<SplitChunks>(), 13000);
+    }
+
+    /**
+     * JPEGs in `testdata` and their expected capacity. The images were written by jpeg-encoder and
+     * libjpeg with different sampling factors, restart intervals and Huffman tables. The
+     * capacities were not recorded from steghide: they were counted from the coefficients libjpeg
+     * reads from each image. CI installs steghide and runs
+     * `test_native_capacity_matches_steghide`, which checks them against `steghide --info`.
+     */
+    const FIXTURES: [(&str, u64); 4] = [
+        ("gradient-gray.jpg", 23),
+        ("libjpeg-optimized.jpg", 239),
+        ("noise-420-restarts.jpg", 380),
+        ("noise-444.jpg", 445),
+    ];
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    #[test]
+    fn test_native_capacity_of_fixtures() {
+        for (name, capacity) in FIXTURES {
+            assert_eq!(
+                jpeg::steghide_capacity(&fixture(name)),
+                Some(capacity),
+                "{}",
+                name
+            );
+        }
+
+        // noise-444.jpg cut off halfway through its scan
+        assert_eq!(jpeg::steghide_capacity(&fixture("truncated.jpg")), None);
+    }
+
+    #[test]
+    #[ignore = "requires steghide"]
+    fn test_native_capacity_matches_steghide() {
+        for (name, capacity) in FIXTURES {
+            let output = Command::new("steghide")
+                .arg("--info")
+                .arg(fixture(name))
+                .stdin(Stdio::null())
+                .output()
+                .expect("steghide could not be run");
+            let info = String::from_utf8_lossy(&output.stdout);
+            let reported = info
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("capacity:"))
+                .unwrap()
+                .trim()
+                .to_string();
+
+            assert_eq!(reported, steghide_format(capacity), "{}", name);
+        }
+    }
+}
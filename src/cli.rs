@@ -0,0 +1,355 @@
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use std::path::PathBuf;
+use stegfile::capacity::CapacityBackend;
+use stegfile::discover::CarrierFilter;
+use stegfile::split::SplitMode;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SplitModeEnum {
+    Scrambled,
+    Full,
+}
+
+impl SplitModeEnum {
+    pub fn to_split_mode(self) -> SplitMode {
+        match self {
+            SplitModeEnum::Scrambled => SplitMode::Scrambled,
+            SplitModeEnum::Full => SplitMode::Full,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum PlacementEnum {
+    /// Embed into every image
+    All,
+    /// Embed into as few images as possible, leaving the rest untouched
+    Minimal,
+    /// Spread the file evenly, filling no image past --max-rate percent of its capacity
+    Spread,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CapacityBackendEnum {
+    /// Run steghide --info on every image
+    Steghide,
+    /// Read the capacity from the JPEG directly, which is much faster
+    Native,
+}
+
+impl CapacityBackendEnum {
+    pub fn to_backend(self) -> CapacityBackend {
+        match self {
+            CapacityBackendEnum::Steghide => CapacityBackend::Steghide,
+            CapacityBackendEnum::Native => CapacityBackend::Native,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum OutputFormatEnum {
+    /// Sentences meant for people
+    Text,
+    /// One JSON object, as documented on the report types of the library
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum LogFormatEnum {
+    /// One line of text per message
+    Text,
+    /// One JSON object per message
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SymlinkPolicyEnum {
+    Follow,
+    Skip,
+}
+
+/**
+ * Parse dimensions written as `<width>x<height>`.
+ */
+fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("expected <width>x<height>, got {}", value))?;
+    let width = width
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad width in {}", value))?;
+    let height = height
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad height in {}", value))?;
+    Ok((width, height))
+}
+
+/**
+ * Parse a number of jobs, which has to be at least one.
+ */
+fn parse_jobs(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("at least one job is needed".to_string()),
+        Ok(jobs) => Ok(jobs),
+        Err(_) => Err(format!("expected a number of jobs, got {}", value)),
+    }
+}
+
+/*
+ * Options choosing which images in `image_dir` are used. This is a plain comment because clap
+ * would show a doc comment as the description of every subcommand it is flattened into.
+ */
+#[derive(Args)]
+pub struct SelectionArgs {
+    #[arg(
+        long,
+        value_name = "GLOB",
+        long_help = "Only use images whose path relative to image_dir matches this pattern. Can be repeated"
+    )]
+    pub include: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        long_help = "Skip images and directories whose path relative to image_dir matches this pattern. Can be repeated"
+    )]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long,
+        long_help = "How many levels of subdirectories to search. 0 only searches image_dir itself"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        long_help = "Skip images smaller than this"
+    )]
+    pub min_size: Option<u64>,
+
+    #[arg(long, value_name = "BYTES", long_help = "Skip images larger than this")]
+    pub max_size: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_dimensions,
+        long_help = "Skip images narrower or shorter than this many pixels"
+    )]
+    pub min_dimensions: Option<(u32, u32)>,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_dimensions,
+        long_help = "Skip images wider or taller than this many pixels"
+    )]
+    pub max_dimensions: Option<(u32, u32)>,
+
+    #[arg(
+        long,
+        default_value = "follow",
+        long_help = "Whether symlinked images and directories are used"
+    )]
+    pub symlinks: SymlinkPolicyEnum,
+
+    #[arg(
+        long,
+        value_name = "LIST",
+        long_help = "Use the images listed in this file, one per line, instead of searching image_dir. Relative paths are relative to image_dir"
+    )]
+    pub files_from: Option<PathBuf>,
+}
+
+impl SelectionArgs {
+    pub fn to_filter(&self) -> CarrierFilter {
+        CarrierFilter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            max_depth: self.max_depth,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            min_dimensions: self.min_dimensions,
+            max_dimensions: self.max_dimensions,
+            follow_symlinks: self.symlinks == SymlinkPolicyEnum::Follow,
+            files_from: self.files_from.clone(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    Extract {
+        image_dir: String,
+        passphrase: String,
+
+        #[arg(long_help = "Where to write the extracted file, or - to write it to stdout")]
+        output_file: String,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    Embed {
+        image_dir: String,
+        passphrase: String,
+
+        #[arg(long_help = "File to hide, or - to read it from stdin")]
+        input_file: String,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+
+        #[arg(
+            long,
+            default_value = "all",
+            long_help = "Which images receive a piece of the file"
+        )]
+        placement: PlacementEnum,
+
+        #[arg(
+            long,
+            default_value = "20",
+            value_parser = clap::value_parser!(u8).range(1..=100),
+            long_help = "Most of each image's capacity to use, in percent, with --placement spread"
+        )]
+        max_rate: u8,
+
+        #[arg(
+            long,
+            long_help = "Pad every image that receives a piece with random chaff to the same size, so they all look equally modified. Steghide is run without encryption, so the chaff can be told apart from the hidden data unless the file being hidden is encrypted"
+        )]
+        chaff: bool,
+
+        #[arg(
+            long,
+            long_help = "Write the stego images here, mirroring their paths in image_dir, instead of modifying image_dir"
+        )]
+        output_dir: Option<String>,
+
+        #[arg(
+            long,
+            requires = "output_dir",
+            long_help = "Also copy the images that were not needed to output_dir"
+        )]
+        copy_unused: bool,
+
+        #[arg(
+            long,
+            long_help = "Continue an interrupted embed of the same file with the same split plan"
+        )]
+        resume: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "resume",
+            long_help = "Throw away an interrupted embed that can't be resumed and start over, putting back any images it had already replaced"
+        )]
+        discard: bool,
+
+        #[arg(
+            long,
+            default_value = "steghide",
+            long_help = "How the capacity of each image is found when planning the split"
+        )]
+        backend: CapacityBackendEnum,
+    },
+    Capacity {
+        image_dir: String,
+
+        #[command(flatten)]
+        selection: SelectionArgs,
+
+        #[arg(
+            long,
+            default_value = "100",
+            value_parser = clap::value_parser!(u8).range(1..=100),
+            long_help = "Report capacity when filling no image past this percent of its capacity"
+        )]
+        max_rate: u8,
+
+        #[arg(
+            long,
+            default_value = "steghide",
+            long_help = "How the capacity of each image is found"
+        )]
+        backend: CapacityBackendEnum,
+    },
+}
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)] // Read from `Cargo.toml`
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+
+    #[arg(
+        long,
+        short = 'm',
+        default_value = "full",
+        long_help = "How data is / was split between images"
+    )]
+    pub split_mode: SplitModeEnum,
+
+    #[arg(
+        long,
+        global = true,
+        default_value = "text",
+        long_help = "How the result is printed to stdout, or to stderr when extracting to stdout. With json, a failed run prints {\"error\": {\"kind\": ..., \"message\": ...}} instead of its report"
+    )]
+    pub format: OutputFormatEnum,
+
+    #[arg(
+        long,
+        short = 'j',
+        global = true,
+        default_value_t = stegfile::default_jobs(),
+        value_parser = parse_jobs,
+        long_help = "How many images are worked on at once. Defaults to the number of CPUs"
+    )]
+    pub jobs: usize,
+
+    #[arg(
+        long,
+        short = 'v',
+        global = true,
+        action = ArgAction::Count,
+        long_help = "Log more. -v logs what is being done, -vv also logs every steghide run, temporary file and piece size"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        long,
+        short = 'q',
+        global = true,
+        conflicts_with = "verbose",
+        long_help = "Only log errors, and don't show progress"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        default_value = "text",
+        long_help = "How messages are logged to stderr"
+    )]
+    pub log_format: LogFormatEnum,
+}
+
+impl Cli {
+    /**
+     * Most detailed level of messages to log.
+     */
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Warn,
+            (false, 1) => LevelFilter::Info,
+            (false, 2) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+}
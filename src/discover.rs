@@ -0,0 +1,439 @@
+use crate::error::{Error, Result};
+use crate::events::{Event, Observer};
+use crate::journal::WORK_DIR_NAME;
+use crate::jpeg;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/**
+ * Which files under an image directory may be used as carriers. Glob patterns are matched
+ * against paths relative to the image directory, e.g. `2020/**` or `**/IMG_*.jpg`.
+ */
+#[derive(Debug, Clone)]
+pub struct CarrierFilter {
+    /// If not empty, only files matching one of these patterns are used.
+    pub include: Vec<String>,
+    /// Files and directories matching any of these patterns are skipped.
+    pub exclude: Vec<String>,
+    /// How many levels of subdirectories to descend into. `Some(0)` only searches the image
+    /// directory itself.
+    pub max_depth: Option<usize>,
+    /// Smallest and largest file size in bytes.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Smallest and largest (width, height) in pixels. Both have to be within bounds.
+    pub min_dimensions: Option<(u32, u32)>,
+    pub max_dimensions: Option<(u32, u32)>,
+    /// Whether symlinked files and directories are used, or skipped.
+    pub follow_symlinks: bool,
+    /// Use the files listed in this file, one per line, instead of searching the image directory.
+    /// Relative paths are relative to the image directory.
+    pub files_from: Option<PathBuf>,
+}
+
+impl Default for CarrierFilter {
+    fn default() -> Self {
+        CarrierFilter {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            min_dimensions: None,
+            max_dimensions: None,
+            follow_symlinks: true,
+            files_from: None,
+        }
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| Error::InvalidPattern {
+            pattern: pattern.clone(),
+            message: e.kind().to_string(),
+        })?;
+        builder.add(glob);
+    }
+
+    builder.build().map_err(|e| Error::InvalidPattern {
+        pattern: patterns.join(" "),
+        message: e.to_string(),
+    })
+}
+
+fn is_jpg(path: &Path) -> bool {
+    let extension = path.extension().and_then(|s| s.to_str());
+    extension == Some("jpg")
+        || extension == Some("JPG")
+        || extension == Some("JPEG")
+        || extension == Some("jpeg")
+}
+
+fn within(value: (u32, u32), min: Option<(u32, u32)>, max: Option<(u32, u32)>) -> bool {
+    let (width, height) = value;
+    let above_min = min.is_none_or(|(w, h)| width >= w && height >= h);
+    let below_max = max.is_none_or(|(w, h)| width <= w && height <= h);
+    above_min && below_max
+}
+
+/**
+ * A `CarrierFilter` with its patterns compiled.
+ */
+struct Selector<'a> {
+    filter: &'a CarrierFilter,
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Selector<'_> {
+    /**
+     * Whether the file at `path`, found at `relative` under the image directory, passes the
+     * filter.
+     */
+    fn accepts(&self, path: &Path, relative: &Path) -> Result<bool> {
+        if self.exclude.is_match(relative) {
+            return Ok(false);
+        }
+        if !self.filter.include.is_empty() && !self.include.is_match(relative) {
+            return Ok(false);
+        }
+
+        let size = fs::metadata(path).map_err(|e| Error::io(path, e))?.len();
+        if self.filter.min_size.is_some_and(|min| size < min)
+            || self.filter.max_size.is_some_and(|max| size > max)
+        {
+            return Ok(false);
+        }
+
+        if self.filter.min_dimensions.is_some() || self.filter.max_dimensions.is_some() {
+            // Files whose dimensions can't be read can't be shown to be within bounds
+            return Ok(jpeg::dimensions(path).is_some_and(|dimensions| {
+                within(
+                    dimensions,
+                    self.filter.min_dimensions,
+                    self.filter.max_dimensions,
+                )
+            }));
+        }
+
+        Ok(true)
+    }
+
+    /**
+     * Pushes all jpg images that pass the filter from recursively searching `dir` to `images`.
+     * `visited` holds the directories already searched, so that symlinks can't loop. Symlinks to
+     * nothing are skipped with a warning to `observer`.
+     */
+    fn walk(
+        &self,
+        root: &Path,
+        dir: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+        images: &mut Vec<PathBuf>,
+        observer: &dyn Observer,
+    ) -> Result<()> {
+        let canonical = dir.canonicalize().map_err(|e| Error::io(dir, e))?;
+        if !visited.insert(canonical) {
+            return Ok(());
+        }
+
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)
+            .map_err(|e| Error::io(dir, e))?
+            .collect::<std::io::Result<_>>()
+            .map_err(|e| Error::io(dir, e))?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let file_type = entry.file_type().map_err(|e| Error::io(&path, e))?;
+
+            if file_type.is_symlink() && !self.filter.follow_symlinks {
+                continue;
+            }
+            // A symlink whose target is gone is no reason to give up on the rest of the tree
+            if file_type.is_symlink() && !path.exists() {
+                observer.event(&Event::Warning {
+                    message: format!("skipping {}, its target does not exist", path.display()),
+                });
+                continue;
+            }
+
+            if path.is_dir() {
+                let too_deep = self.filter.max_depth.is_some_and(|max| depth >= max);
+                if entry.file_name() != WORK_DIR_NAME
+                    && !too_deep
+                    && !self.exclude.is_match(relative)
+                {
+                    self.walk(root, &path, depth + 1, visited, images, observer)?;
+                }
+            } else if is_jpg(&path) && self.accepts(&path, relative)? {
+                images.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Read the list of files in `list`, keeping the ones that pass the filter.
+     */
+    fn read_list(&self, root: &Path, list: &Path) -> Result<Vec<PathBuf>> {
+        let contents = fs::read_to_string(list).map_err(|e| Error::io(list, e))?;
+        let mut images = Vec::new();
+
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let path = root.join(line);
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+
+            if !path.is_file() {
+                return Err(Error::Io {
+                    path: path.display().to_string(),
+                    message: format!("listed in {} but not a file", list.display()),
+                });
+            }
+            if self.accepts(&path, &relative)? {
+                images.push(path);
+            }
+        }
+
+        Ok(images)
+    }
+}
+
+/**
+ * Find the images under `dir` that pass `filter`, telling `observer` about each one. Unless
+ * `filter` lists the files to use, `dir` is searched recursively for jpg images. The work
+ * directory of an interrupted embed and symlinks to nothing are skipped.
+ *
+ * Entries of each directory are visited sorted by name, so the same tree always gives the same
+ * order regardless of the order the filesystem lists them in. Every image is returned once, under
+ * the canonical `dir` at the path it was found at, so that images behind a symlinked directory
+ * keep their place in the tree. Images outside of `dir` are returned by their canonical path.
+ */
+pub fn find_jpg_images(
+    dir: &Path,
+    filter: &CarrierFilter,
+    observer: &dyn Observer,
+) -> Result<Vec<String>> {
+    if !dir.is_dir() {
+        return Err(Error::NotADirectory {
+            path: dir.display().to_string(),
+        });
+    }
+
+    let selector = Selector {
+        filter,
+        include: build_glob_set(&filter.include)?,
+        exclude: build_glob_set(&filter.exclude)?,
+    };
+
+    let root = dir.canonicalize().map_err(|e| Error::io(dir, e))?;
+    let found = match &filter.files_from {
+        Some(list) => selector.read_list(&root, list)?,
+        None => {
+            let mut found = Vec::new();
+            selector.walk(&root, &root, 0, &mut HashSet::new(), &mut found, observer)?;
+            found
+        }
+    };
+
+    // The same image may be reached more than once through symlinks
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut images = Vec::new();
+    for path in found {
+        let canonical = path.canonicalize().map_err(|e| Error::io(&path, e))?;
+        if !seen.insert(canonical.clone()) {
+            continue;
+        }
+
+        let image = match path.strip_prefix(&root) {
+            Ok(relative)
+                if relative
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_))) =>
+            {
+                path.to_string_lossy().to_string()
+            }
+            _ => canonical.to_string_lossy().to_string(),
+        };
+        observer.event(&Event::ImageFound {
+            image: image.clone(),
+        });
+        images.push(image);
+    }
+
+    observer.event(&Event::DiscoveryFinished {
+        images: images.len(),
+    });
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Silent;
+    use tempfile::TempDir;
+
+    fn tree() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for (path, size) in [
+            ("a.jpg", 10),
+            ("b.JPEG", 20),
+            ("notes.txt", 10),
+            ("2020/c.jpg", 30),
+            ("2020/trip/d.jpg", 40),
+            ("raw/e.jpg", 50),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        }
+        dir
+    }
+
+    fn names(dir: &TempDir, filter: &CarrierFilter) -> Vec<String> {
+        let root = dir.path().canonicalize().unwrap();
+        find_jpg_images(dir.path(), filter, &Silent)
+            .unwrap()
+            .iter()
+            .map(|image| {
+                let relative = Path::new(image).strip_prefix(&root).unwrap();
+                relative.to_string_lossy().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_jpg_images() {
+        let dir = tree();
+        assert_eq!(
+            names(&dir, &CarrierFilter::default()),
+            vec!(
+                "2020/c.jpg",
+                "2020/trip/d.jpg",
+                "a.jpg",
+                "b.JPEG",
+                "raw/e.jpg"
+            )
+        );
+
+        let filter = CarrierFilter {
+            include: vec!["**/*.jpg".to_string()],
+            exclude: vec!["raw".to_string()],
+            max_depth: Some(1),
+            ..CarrierFilter::default()
+        };
+        assert_eq!(names(&dir, &filter), vec!("2020/c.jpg", "a.jpg"));
+
+        let filter = CarrierFilter {
+            min_size: Some(20),
+            max_size: Some(40),
+            ..CarrierFilter::default()
+        };
+        assert_eq!(
+            names(&dir, &filter),
+            vec!("2020/c.jpg", "2020/trip/d.jpg", "b.JPEG")
+        );
+
+        // None of the files are real JPEGs, so none have dimensions within bounds
+        let filter = CarrierFilter {
+            min_dimensions: Some((1, 1)),
+            ..CarrierFilter::default()
+        };
+        assert!(names(&dir, &filter).is_empty());
+    }
+
+    #[test]
+    fn test_find_jpg_images_reports_events() {
+        let dir = tree();
+        let events = std::sync::Mutex::new(Vec::new());
+        let observer = |event: &Event| events.lock().unwrap().push(event.clone());
+
+        let filter = CarrierFilter {
+            max_depth: Some(0),
+            ..CarrierFilter::default()
+        };
+        let images = find_jpg_images(dir.path(), &filter, &observer).unwrap();
+        assert_eq!(
+            events.into_inner().unwrap(),
+            vec!(
+                Event::ImageFound {
+                    image: images[0].clone()
+                },
+                Event::ImageFound {
+                    image: images[1].clone()
+                },
+                Event::DiscoveryFinished { images: 2 }
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_jpg_images_from_list() {
+        let dir = tree();
+        let list = dir.path().join("list.txt");
+        fs::write(&list, "raw/e.jpg\n\na.jpg\nraw/e.jpg\n").unwrap();
+
+        let filter = CarrierFilter {
+            files_from: Some(list.clone()),
+            ..CarrierFilter::default()
+        };
+        assert_eq!(names(&dir, &filter), vec!("raw/e.jpg", "a.jpg"));
+
+        fs::write(&list, "missing.jpg\n").unwrap();
+        assert!(find_jpg_images(dir.path(), &filter, &Silent).is_err());
+
+        let filter = CarrierFilter {
+            include: vec!["[".to_string()],
+            ..CarrierFilter::default()
+        };
+        assert!(matches!(
+            find_jpg_images(dir.path(), &filter, &Silent),
+            Err(Error::InvalidPattern { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy() {
+        let dir = tree();
+        std::os::unix::fs::symlink(dir.path().join("2020"), dir.path().join("link")).unwrap();
+        // A loop back to the root must not be followed forever
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("2020/loop")).unwrap();
+        // A dangling symlink is skipped instead of failing discovery
+        std::os::unix::fs::symlink(dir.path().join("gone.jpg"), dir.path().join("f.jpg")).unwrap();
+
+        let skip = CarrierFilter {
+            follow_symlinks: false,
+            ..CarrierFilter::default()
+        };
+        assert_eq!(names(&dir, &skip).len(), 5);
+        assert_eq!(names(&dir, &CarrierFilter::default()).len(), 5);
+
+        // Images behind a symlink out of the tree keep the path they were found at
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("a.jpg"), [0u8; 10]).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("outside")).unwrap();
+        assert_eq!(
+            names(&dir, &CarrierFilter::default()),
+            vec!(
+                "2020/c.jpg",
+                "2020/trip/d.jpg",
+                "a.jpg",
+                "b.JPEG",
+                "outside/a.jpg",
+                "raw/e.jpg"
+            )
+        );
+    }
+}
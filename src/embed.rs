@@ -0,0 +1,780 @@
+use crate::capacity::{usable_capacities, CapacityBackend};
+use crate::error::{Error, Result};
+use crate::events::{Event, Observer, Silent};
+use crate::extract::steghide_extract;
+use crate::journal::{payload_hash, Journal, Plan, PlannedCarrier, WORK_DIR_NAME};
+use crate::piece::{decode_piece, encode_piece, set_id_for, PieceHeader, PieceKey};
+use crate::placement::{select_carriers, Placement};
+use crate::pool::{default_jobs, try_parallel_map};
+use crate::split::{Split, SplitChunks, SplitMode, SplitScrambled};
+use crate::steghide;
+use crate::transaction::{check_targets, Transaction};
+use crate::util::{common_directory, create_parent_dirs, order_by_passphrase};
+use log::{debug, info};
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/**
+ * Embed `piece` into `photo_path`, writing the stego image to `stego_path`. `photo_path` itself is
+ * left untouched. The piece is piped to steghide, so it never touches the disk.
+ */
+fn steghide_embed(
+    photo_path: &str,
+    stego_path: &Path,
+    piece: &[u8],
+    passphrase: &str,
+) -> Result<()> {
+    let mut command = Command::new("steghide");
+    command
+        .arg("embed")
+        .args(["-cf", photo_path])
+        .arg("-sf")
+        .arg(stego_path)
+        .args(["-ef", "-"])
+        .args(["-p", passphrase])
+        .args(["-Z", "-N", "-K", "-f", "-q"])
+        .args(["-e", "none"]);
+
+    let output = steghide::output_with_input(&mut command, photo_path, piece)?;
+    steghide::check(&output, photo_path, |reason| Error::EmbedFailed {
+        image: photo_path.to_string(),
+        reason,
+    })
+}
+
+/**
+ * Where stego images are written instead of modifying the images in place. Each image is written
+ * to `output_dir` under its path relative to `image_dir`.
+ */
+#[derive(Debug, Clone)]
+pub struct OutputDir {
+    pub image_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub copy_unused: bool,
+}
+
+impl OutputDir {
+    /**
+     * Path in the output tree that mirrors `image`. Nothing is created until it is written.
+     */
+    fn target_for(&self, image: &str) -> PathBuf {
+        let image = Path::new(image);
+        match image.strip_prefix(&self.image_dir) {
+            Ok(relative)
+                if relative
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_))) =>
+            {
+                self.output_dir.join(relative)
+            }
+            // Images outside of image_dir go in the root of the output tree
+            _ => self
+                .output_dir
+                .join(image.file_name().unwrap_or(image.as_os_str())),
+        }
+    }
+
+    /**
+     * Copy every image in `image_paths` that is not in `carriers` to the output tree unchanged.
+     */
+    fn copy_unused(
+        &self,
+        image_paths: &[String],
+        carriers: &[String],
+        observer: &dyn Observer,
+    ) -> Result<()> {
+        for image in image_paths.iter().filter(|image| !carriers.contains(image)) {
+            let target = self.target_for(image);
+            create_parent_dirs(&target).map_err(|e| Error::io(&target, e))?;
+            fs::copy(image, &target).map_err(|e| Error::io(&target, e))?;
+            observer.event(&Event::CopiedUnused {
+                image: image.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Read the piece back out of every staged carrier and compare it with what was embedded. Chaff
+ * is not compared, as it is generated anew when an interrupted embed is resumed. A staged carrier
+ * that does not match is removed, so that resuming embeds its piece again instead of skipping it.
+ */
+fn verify_staged(
+    transaction: &Transaction,
+    pieces: &[Vec<u8>],
+    key: &PieceKey,
+    passphrase: &str,
+) -> Result<()> {
+    for (carrier, piece) in transaction.carriers().iter().zip(pieces) {
+        debug!("reading {} back", carrier.staged.display());
+        let extracted = steghide_extract(&carrier.staged.to_string_lossy(), passphrase);
+
+        // Finding nothing at all is a failed verification rather than a failed extraction
+        let read_back = match extracted {
+            Ok(read_back) => read_back,
+            Err(Error::NoData { .. }) => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        if decode_piece(key, &read_back) != decode_piece(key, piece) {
+            let failed = Error::VerificationFailed {
+                image: carrier.source.clone(),
+            };
+            return Err(match fs::remove_file(&carrier.staged) {
+                Ok(()) => failed,
+                Err(e) => Error::Failures {
+                    errors: vec![failed, Error::io(&carrier.staged, e)],
+                },
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Throw away the interrupted embed in `work_dir`, putting back every image that its commit had
+ * already replaced. A work directory without a readable journal is only removed if no image has
+ * to be put back, as it can't tell where they go.
+ */
+fn discard_interrupted(work_dir: &Path) -> Result<()> {
+    match Journal::open(work_dir) {
+        Ok(journal) => {
+            let targets: Vec<PathBuf> = journal
+                .plan()
+                .carriers
+                .iter()
+                .map(|carrier| carrier.target.clone())
+                .collect();
+            if Transaction::restore_backups(work_dir, &targets)? {
+                info!("put back the images the interrupted embed had replaced");
+            }
+            journal.finish()
+        }
+        Err(error @ (Error::CorruptJournal { .. } | Error::NoJournal { .. })) => {
+            if Transaction::has_backups(work_dir)? {
+                return Err(error);
+            }
+            fs::remove_dir_all(work_dir).map_err(|e| Error::io(work_dir, e))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/**
+ * Fail unless `payload_size` bytes fit into bins of `capacities` using the split method `T`.
+ */
+fn check_capacity<T: Split>(payload_size: u64, capacities: &[u64]) -> Result<()> {
+    if capacities.is_empty() {
+        return Err(Error::NoImages);
+    }
+
+    let available = T::max_payload(capacities);
+    if payload_size <= available {
+        return Ok(());
+    }
+
+    // Estimate how many more images are needed using the median capacity as a typical image.
+    let mut sorted_capacities = capacities.to_vec();
+    sorted_capacities.sort_unstable();
+    let typical_capacity = sorted_capacities[sorted_capacities.len() / 2];
+    let missing = payload_size - available;
+    let extra_images = if typical_capacity > 0 {
+        missing.div_ceil(typical_capacity)
+    } else {
+        0
+    };
+
+    Err(Error::InsufficientCapacity {
+        needed: payload_size,
+        available,
+        typical_capacity,
+        extra_images,
+    })
+}
+
+/**
+ * Record a new split plan for `input_buffer` in a journal at `work_dir`, embedding into the
+ * images in `image_paths` chosen by `placement` and writing the stego images to `targets`.
+ *
+ * With `chaff`, every carrier is planned to hold no more than the smallest one, and every piece
+ * is padded to the size of the largest, so that all carriers hold the same amount of data.
+ */
+fn create_journal<T: Split>(
+    work_dir: &Path,
+    input_buffer: &[u8],
+    image_paths: &[String],
+    targets: Vec<PathBuf>,
+    options: &EmbedOptions,
+) -> Result<Journal> {
+    if work_dir.exists() {
+        return Err(Error::JournalExists {
+            path: work_dir.display().to_string(),
+        });
+    }
+
+    // Get max byte capacity of each image, minus room for the piece header
+    let capacities = usable_capacities(
+        image_paths,
+        options.capacity_backend,
+        options.jobs,
+        options.observer.as_ref(),
+    )?;
+
+    let selected = select_carriers::<T>(
+        options.placement,
+        input_buffer.len() as u64,
+        &capacities,
+        options.chaff,
+    );
+    let selected_capacities: Vec<u64> = selected.iter().map(|&(_, c)| c).collect();
+    check_capacity::<T>(input_buffer.len() as u64, &selected_capacities)?;
+
+    // No two images that are written out may end up in the same place
+    let copy_unused = options
+        .output
+        .as_ref()
+        .is_some_and(|output| output.copy_unused);
+    check_targets(
+        image_paths
+            .iter()
+            .zip(&targets)
+            .enumerate()
+            .filter(|&(i, _)| copy_unused || selected.iter().any(|&(chosen, _)| chosen == i))
+            .map(|(_, write)| write),
+    )?;
+
+    let pad_to = if options.chaff {
+        let bins = T::split_to_bins(input_buffer, &selected_capacities);
+        bins.iter().map(|bin| bin.len() as u64).max().unwrap_or(0)
+    } else {
+        0
+    };
+
+    let carriers = selected
+        .into_iter()
+        .map(|(i, capacity)| PlannedCarrier {
+            capacity,
+            source: image_paths[i].clone(),
+            target: targets[i].clone(),
+        })
+        .collect();
+
+    Journal::create(
+        work_dir,
+        Plan {
+            payload_hash: payload_hash(input_buffer),
+            split: T::NAME.to_string(),
+            pad_to,
+            carriers,
+        },
+    )
+}
+
+/**
+ * How to embed a payload into a set of images. Everything but the passphrase has a default, and
+ * can be changed one option at a time:
+ *
+ * ```no_run
+ * # use stegfile::embed::EmbedOptions;
+ * # use stegfile::placement::Placement;
+ * # use stegfile::split::SplitMode;
+ * # let images: Vec<String> = Vec::new();
+ * EmbedOptions::new("passphrase")
+ *     .split_mode(SplitMode::Scrambled)
+ *     .placement(Placement::Minimal)
+ *     .chaff(true)
+ *     .embed(b"secret".to_vec(), &images)?;
+ * # Ok::<(), stegfile::Error>(())
+ * ```
+ */
+#[derive(Clone)]
+pub struct EmbedOptions {
+    observer: Arc<dyn Observer>,
+    passphrase: String,
+    split_mode: SplitMode,
+    placement: Placement,
+    chaff: bool,
+    output: Option<OutputDir>,
+    resume: bool,
+    discard: bool,
+    capacity_backend: CapacityBackend,
+    jobs: usize,
+}
+
+impl EmbedOptions {
+    pub fn new(passphrase: &str) -> EmbedOptions {
+        EmbedOptions {
+            observer: Arc::new(Silent),
+            passphrase: passphrase.to_string(),
+            split_mode: SplitMode::default(),
+            placement: Placement::All,
+            chaff: false,
+            output: None,
+            resume: false,
+            discard: false,
+            capacity_backend: CapacityBackend::Steghide,
+            jobs: default_jobs(),
+        }
+    }
+
+    /**
+     * How the payload is split between images. Extracting has to use the same split mode.
+     */
+    pub fn split_mode(mut self, split_mode: SplitMode) -> EmbedOptions {
+        self.split_mode = split_mode;
+        self
+    }
+
+    /**
+     * Which images receive a piece of the payload.
+     */
+    pub fn placement(mut self, placement: Placement) -> EmbedOptions {
+        self.placement = placement;
+        self
+    }
+
+    /**
+     * Whether every image that receives a piece is padded with random chaff to the same size.
+     * Pieces are hidden without steghide's encryption, so the chaff only looks like the data it
+     * pads if the payload is already encrypted.
+     */
+    pub fn chaff(mut self, chaff: bool) -> EmbedOptions {
+        self.chaff = chaff;
+        self
+    }
+
+    /**
+     * Write the stego images to `output` instead of modifying the images.
+     */
+    pub fn output_dir(mut self, output: OutputDir) -> EmbedOptions {
+        self.output = Some(output);
+        self
+    }
+
+    /**
+     * Whether to continue an interrupted embed of the same payload.
+     */
+    pub fn resume(mut self, resume: bool) -> EmbedOptions {
+        self.resume = resume;
+        self
+    }
+
+    /**
+     * Whether to throw away an interrupted embed and start over, for one that can't be resumed.
+     * Images its commit had already replaced are put back first. Ignored when resuming.
+     */
+    pub fn discard(mut self, discard: bool) -> EmbedOptions {
+        self.discard = discard;
+        self
+    }
+
+    /**
+     * How the capacity of each image is found. Steghide has the final say on what fits, so an
+     * estimate that is off makes the embed fail rather than lose data.
+     */
+    pub fn capacity_backend(mut self, backend: CapacityBackend) -> EmbedOptions {
+        self.capacity_backend = backend;
+        self
+    }
+
+    /**
+     * How many images are worked on at once. Defaults to one per CPU.
+     */
+    pub fn jobs(mut self, jobs: usize) -> EmbedOptions {
+        self.jobs = jobs;
+        self
+    }
+
+    /**
+     * Where progress is reported. Nothing is reported by default.
+     */
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> EmbedOptions {
+        self.observer = observer;
+        self
+    }
+
+    /**
+     * Embed `payload` into `image_paths`, as `mul_embed` does with the chosen split mode.
+     */
+    pub fn embed(&self, payload: Vec<u8>, image_paths: &[String]) -> Result<EmbedReport> {
+        match self.split_mode {
+            SplitMode::Scrambled => mul_embed::<SplitScrambled>(payload, image_paths, self),
+            SplitMode::Full => mul_embed::<SplitChunks>(payload, image_paths, self),
+        }
+    }
+}
+
+/**
+ * What became of one piece of an embed. `bytes` counts the part of the payload in the piece, and
+ * `embedded_bytes` everything hidden in the image, including the piece header and any chaff.
+ * `seconds` is how long steghide took to embed it, which is 0 for a piece embedded by the run
+ * that was resumed.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PieceReport {
+    pub index: usize,
+    pub image: String,
+    pub target: String,
+    pub capacity: u64,
+    pub bytes: u64,
+    pub embedded_bytes: u64,
+    pub resumed: bool,
+    pub seconds: f64,
+}
+
+/**
+ * Outcome of a successful embed: which piece went into which image, and the images that
+ * received none. As JSON:
+ *
+ * ```json
+ * {
+ *   "split": "full",
+ *   "payload_bytes": 5000,
+ *   "pieces": [
+ *     {"index": 0, "image": "a.jpg", "target": "a.jpg", "capacity": 4000, "bytes": 4000,
+ *      "embedded_bytes": 4100, "resumed": false, "seconds": 1.2},
+ *     ...
+ *   ],
+ *   "unused": ["c.jpg"],
+ *   "seconds": 3.4
+ * }
+ * ```
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmbedReport {
+    pub split: String,
+    pub payload_bytes: u64,
+    pub pieces: Vec<PieceReport>,
+    pub unused: Vec<String>,
+    pub seconds: f64,
+}
+
+/**
+ * Embed data from a buffer into multiple files using the split method `T`, into the images
+ * chosen by the placement of `options`. With chaff, every carrier is padded with random data to
+ * the same size; a warning is reported that the chaff only blends in if the payload is encrypted.
+ * The stego images are staged and read back first; the images are only replaced once every one of
+ * them has been embedded and verified, so a failure leaves all of them untouched. With an output
+ * directory the images are never modified and the stego images are written there instead.
+ *
+ * Which image gets which piece depends on the passphrase and the paths of the images relative to
+ * the image directory of the output, or else to the directory they share, but not on the order of
+ * `image_paths`.
+ *
+ * Progress is kept in a journal next to the images. If an embed is interrupted, calling this
+ * again with resume set continues it with the same split plan, skipping the pieces that were
+ * already embedded. With discard set, it is thrown away instead and a new plan is made. If any
+ * piece can't be embedded, no new pieces are started and every piece that failed is reported.
+*/
+pub fn mul_embed<T: Split>(
+    input_buffer: Vec<u8>,
+    image_paths: &[String],
+    options: &EmbedOptions,
+) -> Result<EmbedReport> {
+    let started = Instant::now();
+    let passphrase = options.passphrase.as_str();
+    let output = options.output.as_ref();
+    let observer = options.observer.as_ref();
+
+    if image_paths.is_empty() {
+        return Err(Error::NoImages);
+    }
+    if options.chaff {
+        observer.event(&Event::Warning {
+            message: "steghide is run without encryption, so the random chaff can be told apart \
+                      from the hidden data unless the file being hidden is encrypted"
+                .to_string(),
+        });
+    }
+
+    let mut image_paths = image_paths.to_vec();
+    let image_root = match output {
+        Some(output) => output.image_dir.clone(),
+        None => common_directory(&image_paths.iter().map(PathBuf::from).collect::<Vec<_>>()),
+    };
+    order_by_passphrase(&mut image_paths, &image_root, passphrase);
+    let image_paths = image_paths.as_slice();
+
+    let targets: Vec<PathBuf> = match output {
+        Some(output) => image_paths
+            .iter()
+            .map(|image| output.target_for(image))
+            .collect(),
+        None => image_paths.iter().map(PathBuf::from).collect(),
+    };
+    let work_dir = common_directory(&targets).join(WORK_DIR_NAME);
+
+    debug!("journal is in {}", work_dir.display());
+    let journal = if options.resume {
+        info!("resuming an interrupted embed");
+        let journal = Journal::open(&work_dir)?;
+        journal.check(&payload_hash(&input_buffer), T::NAME)?;
+        journal
+    } else {
+        if options.discard && work_dir.exists() {
+            info!("discarding the interrupted embed in {}", work_dir.display());
+            discard_interrupted(&work_dir)?;
+        }
+        create_journal::<T>(&work_dir, &input_buffer, image_paths, targets, options)?
+    };
+
+    let plan = journal.plan().clone();
+    let carriers: Vec<String> = plan.carriers.iter().map(|c| c.source.clone()).collect();
+    let targets: Vec<PathBuf> = plan.carriers.iter().map(|c| c.target.clone()).collect();
+    let capacities: Vec<u64> = plan.carriers.iter().map(|c| c.capacity).collect();
+
+    info!(
+        "embedding into {} of {} images using the {} split",
+        carriers.len(),
+        image_paths.len(),
+        T::NAME
+    );
+
+    // Split content
+    let split_content = T::split_to_bins(&input_buffer, &capacities);
+    let bin_sizes: Vec<u64> = split_content.iter().map(|bin| bin.len() as u64).collect();
+    debug!(
+        "split {} bytes into pieces of {:?} bytes",
+        input_buffer.len(),
+        split_content.iter().map(Vec::len).collect::<Vec<usize>>()
+    );
+    observer.event(&Event::Split {
+        pieces: split_content.len(),
+        bytes: input_buffer.len() as u64,
+    });
+
+    let key = PieceKey::derive(passphrase);
+    let set_id = set_id_for(&input_buffer);
+    let piece_count = split_content.len() as u64;
+    let mut pieces: Vec<Vec<u8>> = Vec::with_capacity(split_content.len());
+    for (index, bin) in split_content.into_iter().enumerate() {
+        // Prepend the bin with a sealed header holding its piece number, the size of the set and
+        // its length
+        let header = PieceHeader {
+            index: index as u64,
+            count: piece_count,
+            length: bin.len() as u64,
+            set_id,
+        };
+        let bucket = encode_piece(&key, header, &bin, plan.pad_to as usize);
+        debug!("piece {} is {} bytes", index, bucket.len());
+        pieces.push(bucket);
+    }
+
+    // Embed each file piece into a staged copy of its associated image
+    observer.event(&Event::EmbedStarted {
+        pieces: pieces.len(),
+        bytes: pieces.iter().map(|piece| piece.len() as u64).sum(),
+    });
+    let transaction = Transaction::new(journal.dir(), &carriers, &targets)?;
+
+    let piece_sizes: Vec<u64> = pieces.iter().map(|piece| piece.len() as u64).collect();
+
+    // Skip every piece that the run being resumed already embedded
+    let mut pending: Vec<usize> = Vec::new();
+    for (i, carrier) in transaction.carriers().iter().enumerate() {
+        if journal.is_done(i) && carrier.staged.exists() {
+            observer.event(&Event::PieceSkipped {
+                image: carrier.source.clone(),
+                index: i,
+                bytes: piece_sizes[i],
+            });
+        } else {
+            pending.push(i);
+        }
+    }
+
+    // Embed each piece into a staged copy of its image, recording it as soon as it is done so
+    // that an interrupted run can skip it. Once a piece fails, no new pieces are started.
+    let shared_journal = Mutex::new(journal);
+    let results = try_parallel_map(&pending, options.jobs, |&index| {
+        let carrier = &transaction.carriers()[index];
+        debug!("staging {} at {}", carrier.source, carrier.staged.display());
+        observer.event(&Event::PieceStarted {
+            image: carrier.source.clone(),
+            index,
+            bytes: piece_sizes[index],
+        });
+
+        let started = Instant::now();
+        let result = steghide_embed(&carrier.source, &carrier.staged, &pieces[index], passphrase)
+            .and_then(|_| shared_journal.lock().unwrap().mark_done(index));
+        observer.event(&match &result {
+            Ok(()) => Event::PieceEmbedded {
+                image: carrier.source.clone(),
+                index,
+                bytes: piece_sizes[index],
+            },
+            Err(error) => Event::PieceFailed {
+                image: carrier.source.clone(),
+                index,
+                error: error.clone(),
+            },
+        });
+        result.map(|_| started.elapsed())
+    });
+    let journal = shared_journal.into_inner().unwrap();
+
+    let mut errors: Vec<Error> = Vec::new();
+    let mut timings: Vec<Option<Duration>> = vec![None; carriers.len()];
+    for (&index, result) in pending.iter().zip(results) {
+        match result {
+            Some(Ok(elapsed)) => timings[index] = Some(elapsed),
+            Some(Err(error)) => errors.push(error),
+            None => debug!("piece {} was not embedded as another piece failed", index),
+        }
+    }
+
+    // Nothing has been written to the images yet, so bailing out here leaves them untouched
+    if !errors.is_empty() {
+        return Err(match errors.len() {
+            1 => errors.remove(0),
+            _ => Error::Failures { errors },
+        });
+    }
+
+    observer.event(&Event::Verifying {
+        images: carriers.len(),
+    });
+    verify_staged(&transaction, &pieces, &key, passphrase)?;
+
+    observer.event(&Event::Committing {
+        images: carriers.len(),
+    });
+    transaction.commit()?;
+    journal.finish()?;
+
+    if let Some(output) = output.filter(|output| output.copy_unused) {
+        output.copy_unused(image_paths, &carriers, observer)?;
+    }
+
+    let pieces = plan
+        .carriers
+        .iter()
+        .enumerate()
+        .map(|(index, carrier)| PieceReport {
+            index,
+            image: carrier.source.clone(),
+            target: carrier.target.display().to_string(),
+            capacity: carrier.capacity,
+            bytes: bin_sizes[index],
+            embedded_bytes: piece_sizes[index],
+            resumed: timings[index].is_none(),
+            seconds: timings[index].map_or(0.0, |elapsed| elapsed.as_secs_f64()),
+        })
+        .collect();
+
+    observer.event(&Event::EmbedFinished);
+    Ok(EmbedReport {
+        split: T::NAME.to_string(),
+        payload_bytes: input_buffer.len() as u64,
+        pieces,
+        unused: image_paths
+            .iter()
+            .filter(|image| !carriers.contains(image))
+            .cloned()
+            .collect(),
+        seconds: started.elapsed().as_secs_f64(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::split::{SplitChunks, SplitScrambled};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_embed_options() {
+        for split_mode in [SplitMode::Scrambled, SplitMode::Full] {
+            let options = EmbedOptions::new("secret")
+                .split_mode(split_mode)
+                .placement(Placement::Minimal)
+                .chaff(true);
+            assert_eq!(options.embed(vec![1, 2, 3], &[]), Err(Error::NoImages));
+        }
+    }
+
+    #[test]
+    fn test_check_capacity() {
+        let capacities: Vec<u64> = vec![10, 40, 20];
+
+        assert_eq!(check_capacity::<SplitChunks>(70, &capacities), Ok(()));
+        assert_eq!(check_capacity::<SplitScrambled>(30, &capacities), Ok(()));
+        assert_eq!(
+            check_capacity::<SplitChunks>(111, &capacities),
+            Err(Error::InsufficientCapacity {
+                needed: 111,
+                available: 70,
+                typical_capacity: 20,
+                extra_images: 3
+            })
+        );
+        assert_eq!(
+            check_capacity::<SplitScrambled>(31, &capacities),
+            Err(Error::InsufficientCapacity {
+                needed: 31,
+                available: 30,
+                typical_capacity: 20,
+                extra_images: 1
+            })
+        );
+        assert_eq!(check_capacity::<SplitChunks>(0, &[]), Err(Error::NoImages));
+    }
+
+    #[test]
+    fn test_discard_interrupted() {
+        let dir = TempDir::new().unwrap();
+        let work_dir = dir.path().join(WORK_DIR_NAME);
+        let image = dir.path().join("a.jpg");
+        let plan = Plan {
+            payload_hash: payload_hash(b"secret"),
+            split: SplitChunks::NAME.to_string(),
+            pad_to: 0,
+            carriers: vec![PlannedCarrier {
+                capacity: 10,
+                source: image.display().to_string(),
+                target: image.clone(),
+            }],
+        };
+        Journal::create(&work_dir, plan).unwrap();
+
+        // Killed while committing, after the image was replaced with its stego version
+        fs::write(work_dir.join("backup_0"), b"original").unwrap();
+        fs::write(&image, b"stego").unwrap();
+
+        discard_interrupted(&work_dir).unwrap();
+        assert_eq!(fs::read(&image).unwrap(), b"original");
+        assert!(!work_dir.exists());
+
+        // Without a journal there is nothing to put back, so it is just removed
+        fs::create_dir(&work_dir).unwrap();
+        discard_interrupted(&work_dir).unwrap();
+        assert!(!work_dir.exists());
+    }
+
+    #[test]
+    fn test_output_dir_mirrors_image_dir() {
+        let output_root = TempDir::new().unwrap();
+        let output = OutputDir {
+            image_dir: PathBuf::from("/photos"),
+            output_dir: output_root.path().to_path_buf(),
+            copy_unused: false,
+        };
+
+        let target = output.target_for("/photos/2020/trip/a.jpg");
+        assert_eq!(target, output_root.path().join("2020/trip/a.jpg"));
+        // Directories are only created once something is written to them
+        assert!(!output_root.path().join("2020").exists());
+
+        // Paths that leave image_dir go in the root of the output tree instead
+        let target = output.target_for("/photos/../elsewhere/b.jpg");
+        assert_eq!(target, output_root.path().join("b.jpg"));
+    }
+}
@@ -0,0 +1,284 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+/**
+ * Everything that can go wrong while embedding into or extracting from a set of images. As JSON,
+ * an error is an object whose `kind` is the variant in snake case, e.g.
+ * `{"kind": "no_data", "image": "a.jpg"}`, alongside the fields of the variant.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    /// The data extracted from `image` is too short to contain a piece header, or the data the
+    /// header declares.
+    TruncatedPiece { image: String },
+
+    /// The piece in `image` was not sealed with this passphrase, or has been modified.
+    TamperedPiece { image: String },
+
+    /// The piece in `image` is in format `version`, which this version of stegfile cannot read.
+    /// Pieces hidden before the header was sealed read as version 0.
+    UnsupportedVersion { image: String, version: u8 },
+
+    /// The piece in `image` belongs to a different embed than the rest of the set.
+    ForeignPiece { image: String },
+
+    /// The header in `image` declares a different number of pieces than the rest of the set.
+    PieceCountMismatch {
+        image: String,
+        expected: u64,
+        found: u64,
+    },
+
+    /// The header in `image` points past the end of the set.
+    PieceIndexOutOfRange {
+        image: String,
+        index: u64,
+        count: u64,
+    },
+
+    /// More than one image claims to hold the piece at `index`.
+    DuplicatePiece { index: u64, images: Vec<String> },
+
+    /// No image held the pieces at these indices.
+    MissingPieces { missing: Vec<u64> },
+
+    /// None of the images contained a piece.
+    NoPieces,
+
+    /// There are no images to embed into.
+    NoImages,
+
+    /// The payload is `needed` bytes but the images only have room for `available`.
+    /// `extra_images` is a rough estimate of how many more images with `typical_capacity` bytes
+    /// of room would be required.
+    InsufficientCapacity {
+        needed: u64,
+        available: u64,
+        typical_capacity: u64,
+        extra_images: u64,
+    },
+
+    /// `steghide` does not support the format of `image`.
+    UnsupportedFormat { image: String },
+
+    /// `steghide` did not report a capacity for `image`, printing `message` instead.
+    NoCapacity { image: String, message: String },
+
+    /// `steghide` reported a capacity for `image` that could not be read.
+    UnreadableCapacity { image: String, value: String },
+
+    /// The `steghide` binary could not be found.
+    SteghideMissing,
+
+    /// `steghide` found nothing hidden in `image` with the passphrase.
+    NoData { image: String },
+
+    /// No image held anything hidden with the passphrase, so it is most likely wrong.
+    WrongPassphrase,
+
+    /// The piece for `image` is larger than `steghide` can hide in it.
+    CapacityExceeded { image: String },
+
+    /// `steghide` could not extract the piece from `image`.
+    ExtractFailed { image: String, reason: String },
+
+    /// `steghide` could not embed a piece into `image`.
+    EmbedFailed { image: String, reason: String },
+
+    /// Reading the piece back out of the stego version of `image` did not give what was embedded.
+    VerificationFailed { image: String },
+
+    /// Every one of `images` would be written to `target`, so all but one would be lost.
+    TargetCollision { target: String, images: Vec<String> },
+
+    /// `path` is the work directory of an interrupted embed.
+    JournalExists { path: String },
+
+    /// There is no interrupted embed to resume in `path`.
+    NoJournal { path: String },
+
+    /// The interrupted embed in `path` cannot be resumed with the current arguments.
+    JournalMismatch { path: String, reason: String },
+
+    /// The journal in `path` cannot be read.
+    CorruptJournal { path: String },
+
+    /// `path` is not a directory of images.
+    NotADirectory { path: String },
+
+    /// A carrier selection glob pattern is malformed.
+    InvalidPattern { pattern: String, message: String },
+
+    /// A filesystem operation on `path` failed.
+    Io { path: String, message: String },
+
+    /// More than one image failed, each with its own error.
+    Failures { errors: Vec<Error> },
+}
+
+impl Error {
+    /**
+     * An `Io` error for `error`, which happened while working on `path`.
+     */
+    pub fn io(path: &Path, error: std::io::Error) -> Error {
+        Error::Io {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TruncatedPiece { image } => {
+                write!(f, "{} does not contain a valid piece header", image)
+            }
+            Error::TamperedPiece { image } => write!(
+                f,
+                "the piece in {} was not hidden with this passphrase or has been modified",
+                image
+            ),
+            Error::UnsupportedVersion { image, version } => write!(
+                f,
+                "the piece in {} is in format version {}, which this version of stegfile cannot read",
+                image, version
+            ),
+            Error::ForeignPiece { image } => write!(
+                f,
+                "{} holds a piece of a different file than the other images",
+                image
+            ),
+            Error::PieceCountMismatch {
+                image,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} belongs to a set of {} pieces, but the other images belong to a set of {}",
+                image, found, expected
+            ),
+            Error::PieceIndexOutOfRange {
+                image,
+                index,
+                count,
+            } => write!(
+                f,
+                "{} claims to hold piece {}, but the set only has {} pieces",
+                image, index, count
+            ),
+            Error::DuplicatePiece { index, images } => write!(
+                f,
+                "piece {} was found in more than one image: {}",
+                index,
+                images.join(", ")
+            ),
+            Error::MissingPieces { missing } => {
+                let missing: Vec<String> = missing.iter().map(|i| i.to_string()).collect();
+                write!(f, "no image holds piece(s) {}", missing.join(", "))
+            }
+            Error::NoPieces => write!(f, "none of the images contain any pieces"),
+            Error::NoImages => write!(f, "there are no images to embed into"),
+            Error::InsufficientCapacity {
+                needed,
+                available,
+                typical_capacity,
+                extra_images,
+            } => {
+                write!(
+                    f,
+                    "the file is {} bytes but the images only have room for {}, {} bytes short",
+                    needed,
+                    available,
+                    needed - available
+                )?;
+                if *typical_capacity > 0 {
+                    write!(
+                        f,
+                        ". Roughly {} more image(s) holding about {} bytes each are needed",
+                        extra_images, typical_capacity
+                    )?;
+                }
+                Ok(())
+            }
+            Error::UnsupportedFormat { image } => {
+                write!(f, "steghide does not support the format of {}", image)
+            }
+            Error::NoCapacity { image, message } if message.is_empty() => {
+                write!(f, "steghide did not report a capacity for {}", image)
+            }
+            Error::NoCapacity { image, message } => write!(
+                f,
+                "steghide did not report a capacity for {}: {}",
+                image, message
+            ),
+            Error::UnreadableCapacity { image, value } => write!(
+                f,
+                "steghide reported a capacity of \"{}\" for {}, which could not be read",
+                value, image
+            ),
+            Error::SteghideMissing => write!(
+                f,
+                "steghide could not be run. Make sure it is installed and on your PATH"
+            ),
+            Error::NoData { image } => {
+                write!(f, "nothing is hidden in {} with this passphrase", image)
+            }
+            Error::WrongPassphrase => write!(
+                f,
+                "none of the images hold anything hidden with this passphrase. Is it correct?"
+            ),
+            Error::CapacityExceeded { image } => {
+                write!(f, "the piece for {} is too large to hide in it", image)
+            }
+            Error::ExtractFailed { image, reason } => {
+                write!(f, "could not extract from {}: {}", image, reason)
+            }
+            Error::EmbedFailed { image, reason } => {
+                write!(f, "could not embed into {}: {}", image, reason)
+            }
+            Error::VerificationFailed { image } => write!(
+                f,
+                "the data read back from {} does not match what was embedded",
+                image
+            ),
+            Error::TargetCollision { target, images } => write!(
+                f,
+                "{} would all be written to {}",
+                images.join(", "),
+                target
+            ),
+            Error::JournalExists { path } => write!(
+                f,
+                "an interrupted embed was left in {}. Run again with --resume to continue it, or with --discard to throw it away and start over",
+                path
+            ),
+            Error::NoJournal { path } => write!(f, "there is no interrupted embed in {}", path),
+            Error::JournalMismatch { path, reason } => {
+                write!(f, "cannot resume the embed in {}: {}", path, reason)
+            }
+            Error::CorruptJournal { path } => write!(f, "the journal in {} is corrupt", path),
+            Error::NotADirectory { path } => write!(f, "{} is not a directory", path),
+            Error::InvalidPattern { pattern, message } => {
+                write!(f, "invalid pattern {}: {}", pattern, message)
+            }
+            Error::Io { path, message } => write!(f, "{}: {}", path, message),
+            Error::Failures { errors } => {
+                write!(f, "{} images failed", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/**
+ * Result of everything in this crate that can fail.
+ */
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,96 @@
+use crate::error::Error;
+
+/**
+ * Something that happened while discovering, probing, embedding into or extracting from images.
+ * Events about different images may arrive from several threads at once and in any order.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// `image` passes the filter and may be used as a carrier.
+    ImageFound { image: String },
+    /// Discovery is done and found `images` images.
+    DiscoveryFinished { images: usize },
+
+    /// The capacity of `images` images is about to be found.
+    CapacityStarted { images: usize },
+    /// `image` has room for `capacity` bytes of piece data.
+    CapacityFound { image: String, capacity: u64 },
+    /// The capacity of `image` could not be found.
+    CapacityFailed { image: String, error: Error },
+    /// The capacity of every image has been looked at.
+    CapacityFinished,
+
+    /// A payload of `bytes` bytes was split into `pieces` pieces.
+    Split { pieces: usize, bytes: u64 },
+    /// `pieces` pieces totalling `bytes` bytes are about to be embedded.
+    EmbedStarted { pieces: usize, bytes: u64 },
+    /// Piece `index` of `bytes` bytes is being embedded into `image`.
+    PieceStarted {
+        image: String,
+        index: usize,
+        bytes: u64,
+    },
+    /// Piece `index` of `bytes` bytes has been embedded into a staged copy of `image`.
+    PieceEmbedded {
+        image: String,
+        index: usize,
+        bytes: u64,
+    },
+    /// Piece `index` was already embedded into `image` by the run that is being resumed.
+    PieceSkipped {
+        image: String,
+        index: usize,
+        bytes: u64,
+    },
+    /// Piece `index` could not be embedded into `image`.
+    PieceFailed {
+        image: String,
+        index: usize,
+        error: Error,
+    },
+    /// Every staged image is being read back and checked.
+    Verifying { images: usize },
+    /// The staged images are replacing the images.
+    Committing { images: usize },
+    /// `image` was not needed and was copied to the output directory as is.
+    CopiedUnused { image: String },
+    /// Every piece has been embedded and the images have been replaced.
+    EmbedFinished,
+
+    /// `images` images are about to be extracted from.
+    ExtractStarted { images: usize },
+    /// `bytes` bytes were extracted from `image`.
+    Extracted { image: String, bytes: u64 },
+    /// Nothing is hidden in `image` with this passphrase.
+    NothingHidden { image: String },
+    /// Extracting from `image` failed.
+    ExtractFailed { image: String, error: Error },
+    /// The pieces were put back together into a payload of `bytes` bytes.
+    ExtractFinished { bytes: u64 },
+
+    /// Something went wrong that does not stop the operation.
+    Warning { message: String },
+}
+
+/**
+ * Receives every `Event` of an operation, e.g. to show progress. Any `Fn(&Event)` closure that
+ * can be shared between threads is an observer.
+ */
+pub trait Observer: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> Observer for F {
+    fn event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/**
+ * Observer that ignores every event.
+ */
+pub struct Silent;
+
+impl Observer for Silent {
+    fn event(&self, _event: &Event) {}
+}
@@ -0,0 +1,452 @@
+use crate::error::{Error, Result};
+use crate::events::{Event, Observer, Silent};
+use crate::piece::{decode_piece, PieceError, PieceHeader, PieceKey};
+use crate::pool::{default_jobs, try_parallel_map};
+use crate::split::{Split, SplitChunks, SplitMode, SplitScrambled};
+use crate::steghide;
+use crate::util::write_data_to_file;
+use log::debug;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Instant;
+
+/**
+ * Extract whatever is hidden in `photo_path` with `passphrase`. Steghide writes it to a pipe, so
+ * it never touches the disk.
+ */
+pub fn steghide_extract(photo_path: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let mut command = Command::new("steghide");
+    command
+        .arg("extract")
+        .args(["-sf", photo_path])
+        .args(["-p", passphrase])
+        .args(["-xf", "-"])
+        .arg("-q");
+
+    let output = steghide::output(&mut command, photo_path)?;
+    steghide::check(&output, photo_path, |reason| Error::ExtractFailed {
+        image: photo_path.to_string(),
+        reason,
+    })?;
+    Ok(output.stdout)
+}
+
+/**
+ * Put `pieces`, given as (image, raw piece) pairs, back in the order they were split in using
+ * the header of each piece, opened with `key`. Every index of the set must be present exactly
+ * once, and every piece must belong to the same set. Each piece keeps the image it came from, but
+ * loses its header.
+ */
+fn order_pieces(key: &PieceKey, pieces: Vec<(String, Vec<u8>)>) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut expected: Option<PieceHeader> = None;
+    let mut by_index: BTreeMap<u64, Vec<(String, Vec<u8>)>> = BTreeMap::new();
+
+    for (image, piece) in pieces {
+        let (header, data) = match decode_piece(key, &piece) {
+            Ok((header, data)) => (header, data.to_vec()),
+            Err(PieceError::Truncated) => return Err(Error::TruncatedPiece { image }),
+            Err(PieceError::Tampered) => return Err(Error::TamperedPiece { image }),
+            Err(PieceError::UnsupportedVersion(version)) => {
+                return Err(Error::UnsupportedVersion { image, version })
+            }
+        };
+
+        let first = *expected.get_or_insert(header);
+        if header.set_id != first.set_id {
+            return Err(Error::ForeignPiece { image });
+        }
+
+        let count = first.count;
+        if header.count != count {
+            return Err(Error::PieceCountMismatch {
+                image,
+                expected: count,
+                found: header.count,
+            });
+        }
+
+        if header.index >= count {
+            return Err(Error::PieceIndexOutOfRange {
+                image,
+                index: header.index,
+                count,
+            });
+        }
+
+        by_index
+            .entry(header.index)
+            .or_default()
+            .push((image, data));
+    }
+
+    let count = expected.ok_or(Error::NoPieces)?.count;
+
+    if let Some((index, holders)) = by_index.iter().find(|(_, holders)| holders.len() > 1) {
+        return Err(Error::DuplicatePiece {
+            index: *index,
+            images: holders.iter().map(|(image, _)| image.clone()).collect(),
+        });
+    }
+
+    let missing: Vec<u64> = (0..count).filter(|i| !by_index.contains_key(i)).collect();
+    if !missing.is_empty() {
+        return Err(Error::MissingPieces { missing });
+    }
+
+    Ok(by_index
+        .into_values()
+        .map(|mut holders| holders.remove(0))
+        .collect())
+}
+
+/**
+ * Where one piece of the payload was found. `bytes` counts the part of the payload in it.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtractedPiece {
+    pub index: usize,
+    pub image: String,
+    pub bytes: u64,
+}
+
+/**
+ * Outcome of a successful extraction: which image held which piece, and the images that held
+ * nothing. As JSON:
+ *
+ * ```json
+ * {
+ *   "split": "full",
+ *   "payload_bytes": 5000,
+ *   "pieces": [{"index": 0, "image": "a.jpg", "bytes": 4000}, ...],
+ *   "empty": ["c.jpg"],
+ *   "seconds": 2.1
+ * }
+ * ```
+ */
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtractReport {
+    pub split: String,
+    pub payload_bytes: u64,
+    pub pieces: Vec<ExtractedPiece>,
+    pub empty: Vec<String>,
+    pub seconds: f64,
+}
+
+/**
+ * How to extract a payload from a set of images:
+ *
+ * ```no_run
+ * # use stegfile::extract::ExtractOptions;
+ * # use stegfile::split::SplitMode;
+ * # let images: Vec<String> = Vec::new();
+ * let (payload, report) = ExtractOptions::new("passphrase")
+ *     .split_mode(SplitMode::Scrambled)
+ *     .extract(&images)?;
+ * # Ok::<(), stegfile::Error>(())
+ * ```
+ */
+#[derive(Clone)]
+pub struct ExtractOptions {
+    observer: Arc<dyn Observer>,
+    passphrase: String,
+    split_mode: SplitMode,
+    jobs: usize,
+}
+
+impl ExtractOptions {
+    pub fn new(passphrase: &str) -> ExtractOptions {
+        ExtractOptions {
+            observer: Arc::new(Silent),
+            passphrase: passphrase.to_string(),
+            split_mode: SplitMode::default(),
+            jobs: default_jobs(),
+        }
+    }
+
+    /**
+     * How the payload was split between images when it was embedded.
+     */
+    pub fn split_mode(mut self, split_mode: SplitMode) -> ExtractOptions {
+        self.split_mode = split_mode;
+        self
+    }
+
+    /**
+     * Where progress is reported. Nothing is reported by default.
+     */
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> ExtractOptions {
+        self.observer = observer;
+        self
+    }
+
+    /**
+     * How many images are extracted from at once. Defaults to one per CPU.
+     */
+    pub fn jobs(mut self, jobs: usize) -> ExtractOptions {
+        self.jobs = jobs;
+        self
+    }
+
+    /**
+     * Put the payload hidden in `image_paths` back together, as `mul_extract` does with the
+     * chosen split mode.
+     */
+    pub fn extract(&self, image_paths: &[String]) -> Result<(Vec<u8>, ExtractReport)> {
+        match self.split_mode {
+            SplitMode::Scrambled => mul_extract::<SplitScrambled>(image_paths, self),
+            SplitMode::Full => mul_extract::<SplitChunks>(image_paths, self),
+        }
+    }
+
+    /**
+     * Put the payload hidden in `image_paths` back together and write it to `output_path`.
+     */
+    pub fn extract_to(&self, image_paths: &[String], output_path: &Path) -> Result<ExtractReport> {
+        let (payload, report) = self.extract(image_paths)?;
+        write_data_to_file(output_path, &payload)?;
+        Ok(report)
+    }
+}
+
+/**
+ * Reconstructs singular file from a list of image_paths, split with `T`. The order of
+ * `image_paths` does not matter; each piece records its own position in the set. Pieces are read
+ * from as many images at once as `options` allows, then put back together in index order. Images
+ * in a format steghide does not support are skipped with a warning, so extraction only fails
+ * over them if the set can't be completed without them.
+*/
+pub fn mul_extract<T: Split>(
+    image_paths: &[String],
+    options: &ExtractOptions,
+) -> Result<(Vec<u8>, ExtractReport)> {
+    let started = Instant::now();
+    let passphrase = options.passphrase.as_str();
+    let observer = options.observer.as_ref();
+    observer.event(&Event::ExtractStarted {
+        images: image_paths.len(),
+    });
+
+    // Read the piece out of every image, several at a time. Once an image fails, no new images
+    // are started. An image that holds no piece gives the reason instead of failing.
+    let results = try_parallel_map(image_paths, options.jobs, |image| {
+        match steghide_extract(image, passphrase) {
+            Ok(piece) => {
+                observer.event(&Event::Extracted {
+                    image: image.clone(),
+                    bytes: piece.len() as u64,
+                });
+                Ok(Ok(piece))
+            }
+            // Images that were not needed for the payload hold nothing
+            Err(error @ Error::NoData { .. }) => {
+                observer.event(&Event::NothingHidden {
+                    image: image.clone(),
+                });
+                Ok(Err(error))
+            }
+            // Nothing can have been hidden in an image steghide can't read
+            Err(error @ Error::UnsupportedFormat { .. }) => {
+                observer.event(&Event::Warning {
+                    message: format!("{}, skipping it", error),
+                });
+                Ok(Err(error))
+            }
+            Err(error) => {
+                observer.event(&Event::ExtractFailed {
+                    image: image.clone(),
+                    error: error.clone(),
+                });
+                Err(error)
+            }
+        }
+    });
+
+    let mut pieces: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut empty: Vec<String> = Vec::new();
+    let mut errors: Vec<Error> = Vec::new();
+    let mut no_data = false;
+    for (image, result) in image_paths.iter().zip(results) {
+        match result {
+            Some(Ok(Ok(piece))) => pieces.push((image.clone(), piece)),
+            Some(Ok(Err(reason))) => {
+                no_data |= matches!(reason, Error::NoData { .. });
+                empty.push(image.clone());
+            }
+            Some(Err(error)) => errors.push(error),
+            None => debug!("{} was not extracted from as another image failed", image),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(match errors.len() {
+            1 => errors.remove(0),
+            _ => Error::Failures { errors },
+        });
+    }
+
+    // Some images may hold nothing, but if none of the readable ones do the passphrase can't be
+    // right
+    if pieces.is_empty() && no_data {
+        return Err(Error::WrongPassphrase);
+    }
+
+    // The pieces may not necessarily be in order. Use the header of each piece to sort them.
+    let key = PieceKey::derive(passphrase);
+    let (holders, sorted_pieces): (Vec<String>, Vec<Vec<u8>>) =
+        order_pieces(&key, pieces)?.into_iter().unzip();
+    let unified_piece: Vec<u8> = T::join_bins(&sorted_pieces);
+    debug!(
+        "joined {} pieces into {} bytes",
+        sorted_pieces.len(),
+        unified_piece.len()
+    );
+
+    observer.event(&Event::ExtractFinished {
+        bytes: unified_piece.len() as u64,
+    });
+    let report = ExtractReport {
+        split: T::NAME.to_string(),
+        payload_bytes: unified_piece.len() as u64,
+        pieces: holders
+            .into_iter()
+            .zip(&sorted_pieces)
+            .enumerate()
+            .map(|(index, (image, piece))| ExtractedPiece {
+                index,
+                image,
+                bytes: piece.len() as u64,
+            })
+            .collect(),
+        empty,
+        seconds: started.elapsed().as_secs_f64(),
+    };
+    Ok((unified_piece, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::{encode_piece, set_id_for, HEADER_SIZE};
+    use std::sync::OnceLock;
+
+    fn key() -> &'static PieceKey {
+        static KEY: OnceLock<PieceKey> = OnceLock::new();
+        KEY.get_or_init(|| PieceKey::derive("secret"))
+    }
+
+    fn piece_of_set(
+        set: &[u8],
+        image: &str,
+        index: u64,
+        count: u64,
+        body: &[u8],
+    ) -> (String, Vec<u8>) {
+        let header = PieceHeader {
+            index,
+            count,
+            length: body.len() as u64,
+            set_id: set_id_for(set),
+        };
+        (image.to_string(), encode_piece(key(), header, body, 16))
+    }
+
+    fn piece(image: &str, index: u64, count: u64, body: &[u8]) -> (String, Vec<u8>) {
+        piece_of_set(b"payload", image, index, count, body)
+    }
+
+    #[test]
+    fn test_order_pieces() {
+        let pieces = vec![
+            piece("c.jpg", 2, 3, &[]),
+            piece("a.jpg", 0, 3, &[1, 2]),
+            piece("b.jpg", 1, 3, &[3]),
+        ];
+
+        assert_eq!(
+            order_pieces(key(), pieces),
+            Ok(vec!(
+                ("a.jpg".to_string(), vec!(1, 2)),
+                ("b.jpg".to_string(), vec!(3)),
+                ("c.jpg".to_string(), vec!())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_order_pieces_rejects_bad_sets() {
+        let (image, whole) = piece("a.jpg", 0, 1, &[]);
+        assert_eq!(
+            order_pieces(key(), vec![(image, whole[..HEADER_SIZE - 1].to_vec())]),
+            Err(Error::TruncatedPiece {
+                image: "a.jpg".to_string()
+            })
+        );
+        assert_eq!(
+            order_pieces(key(), vec![("a.jpg".to_string(), vec![0; HEADER_SIZE])]),
+            Err(Error::UnsupportedVersion {
+                image: "a.jpg".to_string(),
+                version: 0
+            })
+        );
+        assert_eq!(
+            order_pieces(
+                key(),
+                vec![piece("a.jpg", 0, 2, &[]), piece("b.jpg", 1, 3, &[])]
+            ),
+            Err(Error::PieceCountMismatch {
+                image: "b.jpg".to_string(),
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            order_pieces(key(), vec![piece("a.jpg", 5, 2, &[])]),
+            Err(Error::PieceIndexOutOfRange {
+                image: "a.jpg".to_string(),
+                index: 5,
+                count: 2
+            })
+        );
+        assert_eq!(
+            order_pieces(
+                key(),
+                vec![piece("a.jpg", 1, 2, &[]), piece("b.jpg", 1, 2, &[])]
+            ),
+            Err(Error::DuplicatePiece {
+                index: 1,
+                images: vec!("a.jpg".to_string(), "b.jpg".to_string())
+            })
+        );
+        assert_eq!(
+            order_pieces(key(), vec![piece("a.jpg", 1, 3, &[])]),
+            Err(Error::MissingPieces {
+                missing: vec!(0, 2)
+            })
+        );
+        assert_eq!(order_pieces(key(), Vec::new()), Err(Error::NoPieces));
+        assert_eq!(
+            order_pieces(
+                key(),
+                vec![
+                    piece("a.jpg", 0, 2, &[]),
+                    piece_of_set(b"other", "b.jpg", 1, 2, &[])
+                ]
+            ),
+            Err(Error::ForeignPiece {
+                image: "b.jpg".to_string()
+            })
+        );
+
+        let (image, mut tampered) = piece("a.jpg", 0, 1, &[1, 2]);
+        tampered[HEADER_SIZE] ^= 1;
+        assert_eq!(
+            order_pieces(key(), vec![(image, tampered)]),
+            Err(Error::TamperedPiece {
+                image: "a.jpg".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/**
+ * Name of the directory an embed keeps its journal and staged images in. It lives in the deepest
+ * directory shared by every target image and is removed once the embed is committed.
+ */
+pub const WORK_DIR_NAME: &str = ".stegfile-work";
+
+const JOURNAL_FILE_NAME: &str = "journal";
+
+/**
+ * A carrier in the split plan: how many bytes of the payload it was given room for, the image
+ * it is embedded into and where the stego image ends up.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedCarrier {
+    pub capacity: u64,
+    pub source: String,
+    pub target: PathBuf,
+}
+
+/**
+ * Everything needed to split a payload exactly the same way again.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub payload_hash: String,
+    pub split: String,
+    /// Size every piece is padded to with chaff, or 0 for no chaff.
+    pub pad_to: u64,
+    pub carriers: Vec<PlannedCarrier>,
+}
+
+/**
+ * Append-only record of an embed, so that an interrupted run can be resumed with the same split
+ * plan. The file is line based and tab separated:
+ *
+ * payload <sha256 of the payload>
+ * split <split method>
+ * pad <size every piece is padded to with chaff>
+ * carrier <capacity> <source image> <target image>   (one per piece, in piece order)
+ * done <piece index>                                  (appended as each piece is embedded)
+ *
+ * Backslashes, tabs and newlines in image paths are escaped as `\\`, `\t` and `\n`.
+ */
+pub struct Journal {
+    dir: PathBuf,
+    file: File,
+    plan: Plan,
+    done: BTreeSet<usize>,
+}
+
+pub fn payload_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/**
+ * `field` with every character that would end it escaped.
+ */
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/**
+ * Undo `escape`, or `None` if `field` was not escaped by it.
+ */
+fn unescape(field: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+fn corrupt(path: &Path) -> Error {
+    Error::CorruptJournal {
+        path: path.display().to_string(),
+    }
+}
+
+impl Journal {
+    /**
+     * Create a work directory at `dir` and record `plan` in a new journal inside it.
+     */
+    pub fn create(dir: &Path, plan: Plan) -> Result<Journal> {
+        if dir.exists() {
+            return Err(Error::JournalExists {
+                path: dir.display().to_string(),
+            });
+        }
+        fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+
+        let path = dir.join(JOURNAL_FILE_NAME);
+        let mut contents = format!(
+            "payload\t{}\nsplit\t{}\npad\t{}\n",
+            plan.payload_hash, plan.split, plan.pad_to
+        );
+        for carrier in &plan.carriers {
+            contents.push_str(&format!(
+                "carrier\t{}\t{}\t{}\n",
+                carrier.capacity,
+                escape(&carrier.source),
+                escape(&carrier.target.display().to_string())
+            ));
+        }
+
+        let mut file = File::create(&path).map_err(|e| Error::io(&path, e))?;
+        file.write_all(contents.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::io(&path, e))?;
+
+        Ok(Journal {
+            dir: dir.to_path_buf(),
+            file,
+            plan,
+            done: BTreeSet::new(),
+        })
+    }
+
+    /**
+     * Open the journal of an interrupted embed in `dir`. A last entry that was only partly
+     * written when the embed was killed is dropped from the file, so that new entries start on a
+     * line of their own.
+     */
+    pub fn open(dir: &Path) -> Result<Journal> {
+        let path = dir.join(JOURNAL_FILE_NAME);
+        if !path.exists() {
+            return Err(Error::NoJournal {
+                path: dir.display().to_string(),
+            });
+        }
+
+        let mut contents = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+        let complete = contents.rfind('\n').map_or(0, |end| end + 1);
+        let torn = complete < contents.len();
+        contents.truncate(complete);
+
+        let mut payload_hash: Option<String> = None;
+        let mut split: Option<String> = None;
+        let mut pad_to: Option<u64> = None;
+        let mut carriers: Vec<PlannedCarrier> = Vec::new();
+        let mut done: BTreeSet<usize> = BTreeSet::new();
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["payload", hash] => payload_hash = Some(hash.to_string()),
+                ["split", name] => split = Some(name.to_string()),
+                ["pad", size] => pad_to = Some(size.parse().map_err(|_| corrupt(&path))?),
+                ["carrier", capacity, source, target] => carriers.push(PlannedCarrier {
+                    capacity: capacity.parse().map_err(|_| corrupt(&path))?,
+                    source: unescape(source).ok_or_else(|| corrupt(&path))?,
+                    target: PathBuf::from(unescape(target).ok_or_else(|| corrupt(&path))?),
+                }),
+                ["done", index] => match index.parse::<usize>() {
+                    Ok(index) if index < carriers.len() => {
+                        done.insert(index);
+                    }
+                    _ => return Err(corrupt(&path)),
+                },
+                _ => return Err(corrupt(&path)),
+            }
+        }
+
+        let plan = Plan {
+            payload_hash: payload_hash.ok_or_else(|| corrupt(&path))?,
+            split: split.ok_or_else(|| corrupt(&path))?,
+            pad_to: pad_to.ok_or_else(|| corrupt(&path))?,
+            carriers,
+        };
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::io(&path, e))?;
+        if torn {
+            file.set_len(complete as u64)
+                .and_then(|_| file.sync_data())
+                .map_err(|e| Error::io(&path, e))?;
+        }
+
+        Ok(Journal {
+            dir: dir.to_path_buf(),
+            file,
+            plan,
+            done,
+        })
+    }
+
+    /**
+     * Fail unless this journal was written for the same payload and split method.
+     */
+    pub fn check(&self, payload_hash: &str, split: &str) -> Result<()> {
+        let reason = if self.plan.payload_hash != payload_hash {
+            "the file to embed has changed"
+        } else if self.plan.split != split {
+            "the split mode has changed"
+        } else {
+            return Ok(());
+        };
+
+        Err(Error::JournalMismatch {
+            path: self.dir.display().to_string(),
+            reason: reason.to_string(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    pub fn is_done(&self, index: usize) -> bool {
+        self.done.contains(&index)
+    }
+
+    /**
+     * Record that piece `index` has been embedded into its staged carrier.
+     */
+    pub fn mark_done(&mut self, index: usize) -> Result<()> {
+        let path = self.dir.join(JOURNAL_FILE_NAME);
+        self.file
+            .write_all(format!("done\t{}\n", index).as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| Error::io(&path, e))?;
+
+        self.done.insert(index);
+        Ok(())
+    }
+
+    /**
+     * Remove the work directory once the embed has been committed.
+     */
+    pub fn finish(self) -> Result<()> {
+        fs::remove_dir_all(&self.dir).map_err(|e| Error::io(&self.dir, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_escape() {
+        for field in ["plain.jpg", "a\tb\nc\\d.jpg", "\\t", ""] {
+            assert_eq!(unescape(&escape(field)).as_deref(), Some(field));
+        }
+        assert!(!escape("a\tb\nc").contains(['\t', '\n']));
+        assert_eq!(unescape("a\\"), None);
+        assert_eq!(unescape("a\\x"), None);
+    }
+
+    #[test]
+    fn test_journal_resumes_plan() {
+        let root = TempDir::new().unwrap();
+        let dir = root.path().join(WORK_DIR_NAME);
+        let plan = Plan {
+            payload_hash: payload_hash(b"secret"),
+            split: "full".to_string(),
+            pad_to: 30,
+            carriers: vec![
+                PlannedCarrier {
+                    capacity: 10,
+                    source: "/photos/a b.jpg".to_string(),
+                    target: PathBuf::from("/photos/a b.jpg"),
+                },
+                PlannedCarrier {
+                    capacity: 20,
+                    source: "/photos/c\td\\e\nf.jpg".to_string(),
+                    target: PathBuf::from("/out/c\td\\e\nf.jpg"),
+                },
+            ],
+        };
+
+        let mut journal = Journal::create(&dir, plan.clone()).unwrap();
+        journal.mark_done(1).unwrap();
+        drop(journal);
+
+        // Simulate being killed while writing the next entry
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(JOURNAL_FILE_NAME))
+            .unwrap();
+        file.write_all(b"do").unwrap();
+
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(journal.plan(), &plan);
+        assert!(!journal.is_done(0));
+        assert!(journal.is_done(1));
+        assert!(journal.check(&payload_hash(b"secret"), "full").is_ok());
+        assert!(journal.check(&payload_hash(b"other"), "full").is_err());
+        assert!(journal
+            .check(&payload_hash(b"secret"), "scrambled")
+            .is_err());
+
+        assert!(matches!(
+            Journal::create(&dir, plan.clone()),
+            Err(Error::JournalExists { .. })
+        ));
+
+        // Resuming again after more pieces were recorded still finds all of them
+        let mut journal = Journal::open(&dir).unwrap();
+        journal.mark_done(0).unwrap();
+        drop(journal);
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(journal.plan(), &plan);
+        assert!(journal.is_done(0));
+        assert!(journal.is_done(1));
+        journal.finish().unwrap();
+        assert!(!dir.exists());
+    }
+}
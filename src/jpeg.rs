@@ -0,0 +1,587 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DHT: u8 = 0xC4;
+const DRI: u8 = 0xDD;
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+
+/**
+ * Whether `marker` starts a frame (SOF0 to SOF15), which holds the image dimensions. 0xC4, 0xC8
+ * and 0xCC share the range but are huffman tables, a reserved marker and arithmetic coding
+ * conditioning.
+ */
+fn is_start_of_frame(marker: u8) -> bool {
+    (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC)
+}
+
+/**
+ * Whether `marker` stands alone, without a length and payload following it.
+ */
+fn is_standalone(marker: u8) -> bool {
+    matches!(marker, 0x01 | SOI | EOI | 0xD0..=0xD7)
+}
+
+fn read_u8(reader: &mut impl Read) -> Option<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte).ok()?;
+    Some(byte[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> Option<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes).ok()?;
+    Some(u16::from_be_bytes(bytes))
+}
+
+/**
+ * Read the (width, height) of a JPEG from its frame header. Returns `None` if `reader` is not a
+ * JPEG or ends before the frame header.
+ */
+pub fn read_dimensions(reader: &mut impl Read) -> Option<(u32, u32)> {
+    if read_u8(reader)? != 0xFF || read_u8(reader)? != SOI {
+        return None;
+    }
+
+    loop {
+        if read_u8(reader)? != 0xFF {
+            return None;
+        }
+
+        // Any number of 0xFF may pad the space before a marker
+        let mut marker = read_u8(reader)?;
+        while marker == 0xFF {
+            marker = read_u8(reader)?;
+        }
+
+        if is_standalone(marker) {
+            continue;
+        }
+        if marker == SOS || marker == EOI {
+            return None;
+        }
+
+        let length = read_u16(reader)?;
+        if is_start_of_frame(marker) {
+            let _precision = read_u8(reader)?;
+            let height = read_u16(reader)?;
+            let width = read_u16(reader)?;
+            return Some((width as u32, height as u32));
+        }
+
+        // Skip the rest of the segment; its length includes the two length bytes
+        let skip = (length as u64).checked_sub(2)?;
+        std::io::copy(&mut reader.by_ref().take(skip), &mut std::io::sink()).ok()?;
+    }
+}
+
+/**
+ * (width, height) of the JPEG at `path`, if it is one.
+ */
+pub fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let file = File::open(path).ok()?;
+    read_dimensions(&mut BufReader::new(file))
+}
+
+/**
+ * Huffman table in the form of section F.2.2.3 of the JPEG standard: for every code length, the
+ * largest code of that length and where codes of that length start in `values`.
+ */
+#[derive(Clone)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    value_offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> HuffmanTable {
+        let mut max_code = [-1; 17];
+        let mut value_offset = [0; 17];
+        let mut code = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            value_offset[length] = index - code;
+            code += count;
+            index += count;
+            max_code[length] = if count > 0 { code - 1 } else { -1 };
+            code <<= 1;
+        }
+
+        HuffmanTable {
+            max_code,
+            value_offset,
+            values,
+        }
+    }
+}
+
+/**
+ * Reads the entropy coded data of a scan bit by bit, removing the stuffed zero after every 0xFF.
+ * Once a marker is reached, zeros are read in its place. Reading past the end of the data fails.
+ */
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            position,
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        match *self.data.get(self.position)? {
+            0xFF if self.data.get(self.position + 1) == Some(&0x00) => {
+                self.position += 2;
+                Some(0xFF)
+            }
+            0xFF => Some(0),
+            byte => {
+                self.position += 1;
+                Some(byte)
+            }
+        }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        if self.bits == 0 {
+            self.buffer = self.next_byte()? as u32;
+            self.bits = 8;
+        }
+        self.bits -= 1;
+        Some((self.buffer >> self.bits) & 1)
+    }
+
+    fn bits(&mut self, count: u8) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some((value << 1) | self.bit()?))
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> Option<u8> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | self.bit()? as i32;
+            if code <= table.max_code[length] {
+                let index = table.value_offset[length] + code;
+                return table.values.get(usize::try_from(index).ok()?).copied();
+            }
+        }
+        None
+    }
+
+    /**
+     * Skip the restart marker that should follow, dropping any bits left of the current byte.
+     */
+    fn restart(&mut self) {
+        self.bits = 0;
+        if self.data.get(self.position) == Some(&0xFF)
+            && matches!(self.data.get(self.position + 1), Some(0xD0..=0xD7))
+        {
+            self.position += 2;
+        }
+    }
+}
+
+/**
+ * Value of an `size` bit coefficient as coded in the entropy coded data, per section F.2.2.1.
+ */
+fn extend(value: u32, size: u8) -> i32 {
+    if size == 0 {
+        return 0;
+    }
+    let value = value as i32;
+    if value < 1 << (size - 1) {
+        value - (1 << size) + 1
+    } else {
+        value
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    width_in_blocks: usize,
+    height_in_blocks: usize,
+}
+
+/**
+ * Number of DCT coefficients in a JPEG.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Coefficients {
+    pub total: u64,
+    pub nonzero: u64,
+}
+
+/**
+ * Everything known about a JPEG while its scans are being read.
+ */
+struct Decoder {
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    components: Vec<Component>,
+    max_horizontal: usize,
+    max_vertical: usize,
+    width: usize,
+    height: usize,
+    restart_interval: usize,
+    coefficients: Coefficients,
+}
+
+impl Decoder {
+    fn read_huffman_tables(&mut self, mut segment: &[u8]) -> Option<()> {
+        while !segment.is_empty() {
+            let class = segment[0] >> 4;
+            let id = (segment[0] & 0x0F) as usize;
+            let counts: [u8; 16] = segment.get(1..17)?.try_into().ok()?;
+            let total: usize = counts.iter().map(|&c| c as usize).sum();
+            let values = segment.get(17..17 + total)?.to_vec();
+
+            let table = Some(HuffmanTable::new(&counts, values));
+            match class {
+                0 => *self.dc_tables.get_mut(id)? = table,
+                1 => *self.ac_tables.get_mut(id)? = table,
+                _ => return None,
+            }
+            segment = &segment[17 + total..];
+        }
+        Some(())
+    }
+
+    fn read_frame(&mut self, segment: &[u8]) -> Option<()> {
+        self.height = u16::from_be_bytes(segment.get(1..3)?.try_into().ok()?) as usize;
+        self.width = u16::from_be_bytes(segment.get(3..5)?.try_into().ok()?) as usize;
+        let count = *segment.get(5)? as usize;
+
+        for i in 0..count {
+            let spec = segment.get(6 + i * 3..9 + i * 3)?;
+            self.components.push(Component {
+                id: spec[0],
+                horizontal: (spec[1] >> 4) as usize,
+                vertical: (spec[1] & 0x0F) as usize,
+                width_in_blocks: 0,
+                height_in_blocks: 0,
+            });
+        }
+
+        self.max_horizontal = self.components.iter().map(|c| c.horizontal).max()?;
+        self.max_vertical = self.components.iter().map(|c| c.vertical).max()?;
+        if self.width == 0 || self.height == 0 || self.max_horizontal == 0 || self.max_vertical == 0
+        {
+            return None;
+        }
+
+        for component in &mut self.components {
+            let width = (self.width * component.horizontal).div_ceil(self.max_horizontal);
+            let height = (self.height * component.vertical).div_ceil(self.max_vertical);
+            component.width_in_blocks = width.div_ceil(8);
+            component.height_in_blocks = height.div_ceil(8);
+        }
+        Some(())
+    }
+
+    /**
+     * Count the coefficients of the scan whose header is `segment` and whose entropy coded data
+     * starts at `position` in `data`. Returns where the scan ends.
+     */
+    fn read_scan(&mut self, segment: &[u8], data: &[u8], position: usize) -> Option<usize> {
+        let count = *segment.first()? as usize;
+        let mut scan = Vec::with_capacity(count);
+        for i in 0..count {
+            let spec = segment.get(1 + i * 2..3 + i * 2)?;
+            let component = *self.components.iter().find(|c| c.id == spec[0])?;
+            let dc = self.dc_tables.get((spec[1] >> 4) as usize)?.clone()?;
+            let ac = self.ac_tables.get((spec[1] & 0x0F) as usize)?.clone()?;
+            scan.push((component, dc, ac));
+        }
+
+        // A single component is coded block by block, several are interleaved in MCUs
+        let (mcus_across, mcus_down) = if let [(component, _, _)] = scan.as_slice() {
+            (component.width_in_blocks, component.height_in_blocks)
+        } else {
+            (
+                self.width.div_ceil(8 * self.max_horizontal),
+                self.height.div_ceil(8 * self.max_vertical),
+            )
+        };
+        let single = scan.len() == 1;
+
+        // Every block takes at least two bits, a DC and an end of block code, so a frame declaring
+        // more blocks than the data can hold is corrupt rather than worth decoding
+        let blocks_per_mcu: usize = match single {
+            true => 1,
+            false => scan.iter().map(|(c, _, _)| c.horizontal * c.vertical).sum(),
+        };
+        let blocks = mcus_across * mcus_down * blocks_per_mcu;
+        if blocks > data.len().saturating_sub(position) * 4 {
+            return None;
+        }
+
+        let mut reader = BitReader::new(data, position);
+        let mut predictions = vec![0i32; scan.len()];
+        for mcu in 0..mcus_across * mcus_down {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                reader.restart();
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+
+            let (mcu_x, mcu_y) = (mcu % mcus_across, mcu / mcus_across);
+            for ((component, dc, ac), prediction) in scan.iter().zip(&mut predictions) {
+                let (across, down) = match single {
+                    true => (1, 1),
+                    false => (component.horizontal, component.vertical),
+                };
+                for block in 0..across * down {
+                    let x = mcu_x * across + block % across;
+                    let y = mcu_y * down + block / across;
+                    // Blocks padding the MCU past the edge of the image are not part of it
+                    let counted = x < component.width_in_blocks && y < component.height_in_blocks;
+                    let nonzero = read_block(&mut reader, dc, ac, prediction)?;
+                    if counted {
+                        self.coefficients.total += 64;
+                        self.coefficients.nonzero += nonzero;
+                    }
+                }
+            }
+        }
+
+        Some(reader.position)
+    }
+}
+
+/**
+ * Read one block of coefficients, returning how many are not zero.
+ */
+fn read_block(
+    reader: &mut BitReader,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+    prediction: &mut i32,
+) -> Option<u64> {
+    let size = reader.decode(dc)?;
+    if size > 16 {
+        return None;
+    }
+    *prediction += extend(reader.bits(size)?, size);
+    let mut nonzero = (*prediction != 0) as u64;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = reader.decode(ac)?;
+        let (run, size) = (symbol >> 4, symbol & 0x0F);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+
+        reader.bits(size)?;
+        nonzero += 1;
+        k += run as usize + 1;
+    }
+
+    Some(nonzero)
+}
+
+/**
+ * Count the DCT coefficients of a baseline JPEG by decoding its entropy coded data. Returns `None`
+ * if `data` is not a baseline JPEG, e.g. a progressive one, or is corrupt.
+ */
+pub fn count_coefficients(data: &[u8]) -> Option<Coefficients> {
+    if data.get(..2)? != [0xFF, SOI] {
+        return None;
+    }
+
+    let mut decoder = Decoder {
+        dc_tables: Default::default(),
+        ac_tables: Default::default(),
+        components: Vec::new(),
+        max_horizontal: 0,
+        max_vertical: 0,
+        width: 0,
+        height: 0,
+        restart_interval: 0,
+        coefficients: Coefficients::default(),
+    };
+
+    let mut position = 2;
+    loop {
+        // Skip to the next marker, past any fill bytes and the end of the previous scan
+        while *data.get(position)? != 0xFF
+            || matches!(data.get(position + 1)?, 0x00 | 0xFF | 0xD0..=0xD7)
+        {
+            position += 1;
+        }
+        let marker = data[position + 1];
+        position += 2;
+
+        if marker == EOI {
+            break;
+        }
+        if is_standalone(marker) {
+            continue;
+        }
+
+        let length = u16::from_be_bytes(data.get(position..position + 2)?.try_into().ok()?);
+        let segment = data.get(position + 2..position + length as usize)?;
+        position += length as usize;
+
+        match marker {
+            DHT => decoder.read_huffman_tables(segment)?,
+            DRI => {
+                decoder.restart_interval =
+                    u16::from_be_bytes(segment.get(..2)?.try_into().ok()?) as usize
+            }
+            SOF0 | SOF1 => decoder.read_frame(segment)?,
+            // Progressive, lossless and arithmetic coded frames are not supported
+            marker if is_start_of_frame(marker) => return None,
+            SOS => {
+                if decoder.components.is_empty() {
+                    return None;
+                }
+                position = decoder.read_scan(segment, data, position)?;
+            }
+            _ => {}
+        }
+    }
+
+    if decoder.components.is_empty() {
+        return None;
+    }
+    Some(decoder.coefficients)
+}
+
+/**
+ * Capacity in bytes that steghide reports for the JPEG at `path`, computed without running
+ * steghide. Steghide embeds into the DCT coefficients that are not zero, one bit for every three
+ * coefficients. Returns `None` if the file can't be read or is not a baseline JPEG.
+ */
+pub fn steghide_capacity(path: &Path) -> Option<u64> {
+    let data = std::fs::read(path).ok()?;
+    let coefficients = count_coefficients(&data)?;
+    Some(coefficients.nonzero / 3 / 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+    /**
+     * Encode `pixels` of a `width` x `height` image as a JPEG, letting `configure` change the
+     * encoder's settings first.
+     */
+    fn encode(
+        pixels: &[u8],
+        width: u16,
+        height: u16,
+        color: ColorType,
+        configure: impl FnOnce(&mut Encoder<&mut Vec<u8>>),
+    ) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        let mut encoder = Encoder::new(&mut jpeg, 90);
+        configure(&mut encoder);
+        encoder.encode(pixels, width, height, color).unwrap();
+        jpeg
+    }
+
+    /**
+     * Pseudo random pixels, so that plenty of coefficients are not zero.
+     */
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545F491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_dimensions() {
+        let jpeg: Vec<u8> = vec![
+            0xFF, SOI, // Start of image
+            0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, // APP0 with two bytes of payload
+            0xFF, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x01, 0x2C, 0x00, 0xC8, 0x01, 0x01, 0x11,
+            0x00, // SOF0, 200x300, one component
+            0xFF, EOI,
+        ];
+        assert_eq!(read_dimensions(&mut jpeg.as_slice()), Some((200, 300)));
+
+        let truncated = &jpeg[..12];
+        assert_eq!(read_dimensions(&mut &truncated[..]), None);
+        assert_eq!(read_dimensions(&mut &b"\x89PNG"[..]), None);
+    }
+
+    #[test]
+    fn test_count_coefficients() {
+        // A flat image only has the DC coefficient of each of its 3x2 blocks
+        let flat = encode(&[200; 24 * 16], 24, 16, ColorType::Luma, |_| {});
+        assert_eq!(
+            count_coefficients(&flat),
+            Some(Coefficients {
+                total: 6 * 64,
+                nonzero: 6
+            })
+        );
+
+        // 4:2:0 subsampling pads each MCU to 16x16 pixels, but only 3x3 luma and 2x2 blocks of
+        // each chroma component cover the image
+        let pixels = noise(20 * 20 * 3);
+        let color = encode(&pixels, 20, 20, ColorType::Rgb, |encoder| {
+            encoder.set_sampling_factor(SamplingFactor::F_2_2)
+        });
+        let counted = count_coefficients(&color).unwrap();
+        assert_eq!(counted.total, (9 + 4 + 4) * 64);
+        assert!(counted.nonzero > 17);
+
+        let restarts = encode(&pixels, 20, 20, ColorType::Rgb, |encoder| {
+            encoder.set_sampling_factor(SamplingFactor::F_2_2);
+            encoder.set_restart_interval(1);
+        });
+        assert_eq!(count_coefficients(&restarts), Some(counted));
+
+        let optimized = encode(&pixels, 20, 20, ColorType::Rgb, |encoder| {
+            encoder.set_sampling_factor(SamplingFactor::F_2_2);
+            encoder.set_optimized_huffman_tables(true);
+        });
+        assert_eq!(count_coefficients(&optimized), Some(counted));
+
+        let progressive = encode(&pixels, 20, 20, ColorType::Rgb, |encoder| {
+            encoder.set_progressive(true)
+        });
+        assert_eq!(count_coefficients(&progressive), None);
+        assert_eq!(count_coefficients(&color[..color.len() / 2]), None);
+    }
+
+    #[test]
+    fn test_count_coefficients_of_corrupt_frame() {
+        let mut jpeg = encode(&noise(16 * 16 * 3), 16, 16, ColorType::Rgb, |_| {});
+        assert!(count_coefficients(&jpeg).is_some());
+
+        // A frame claiming to be 65535x65535 with only a few bytes of data is given up on at once
+        let frame = jpeg.windows(2).position(|w| w == [0xFF, SOF0]).unwrap();
+        jpeg[frame + 5..frame + 9].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(count_coefficients(&jpeg), None);
+    }
+}
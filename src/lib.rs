@@ -0,0 +1,33 @@
+//! Hides one file among several images.
+//!
+//! A payload is split into pieces with a [`split::Split`] method, and each piece is hidden in a
+//! JPEG with `steghide`, which has to be installed. Each piece carries a header sealed with a key
+//! derived from the passphrase, so the pieces can be put back together in any order.
+//!
+//! - [`discover`] finds the images to use under a directory.
+//! - [`capacity`] finds how much a set of images can hold.
+//! - [`embed`] hides a payload in a set of images.
+//! - [`extract`] puts a payload back together from a set of images.
+//!
+//! Every fallible operation returns the crate wide [`Error`]. Nothing is printed; progress is
+//! reported as [`events::Event`]s to an [`events::Observer`] instead.
+
+mod cache;
+pub mod capacity;
+pub mod discover;
+pub mod embed;
+pub mod error;
+pub mod events;
+pub mod extract;
+mod journal;
+mod jpeg;
+mod piece;
+pub mod placement;
+mod pool;
+pub mod split;
+mod steghide;
+mod transaction;
+mod util;
+
+pub use error::{Error, Result};
+pub use pool::default_jobs;
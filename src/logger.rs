@@ -0,0 +1,104 @@
+use crate::cli::LogFormatEnum;
+use crate::progress::ProgressReporter;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * Writes log records to stderr, one per line, as text or as JSON objects. Lines are written
+ * around the progress bar so that the two don't garble each other.
+ */
+pub struct Logger {
+    format: LogFormatEnum,
+    reporter: Arc<ProgressReporter>,
+}
+
+impl Logger {
+    /**
+     * Install a logger writing records up to `level` in `format` as the global logger.
+     */
+    pub fn init(
+        level: LevelFilter,
+        format: LogFormatEnum,
+        reporter: Arc<ProgressReporter>,
+    ) -> Result<(), SetLoggerError> {
+        log::set_boxed_logger(Box::new(Logger { format, reporter }))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+/**
+ * `record` as a line of text, e.g. `warning: could not save capacity cache`.
+ */
+fn text_line(record: &Record) -> String {
+    let level = match record.level() {
+        log::Level::Warn => "warning".to_string(),
+        level => level.as_str().to_lowercase(),
+    };
+    format!("{}: {}", level, record.args())
+}
+
+/**
+ * `record` as a JSON object on one line, stamped with `timestamp` in seconds since the epoch.
+ */
+fn json_line(record: &Record, timestamp: f64) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str().to_lowercase(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+    .to_string()
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = match self.format {
+            LogFormatEnum::Text => text_line(record),
+            LogFormatEnum::Json => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs_f64())
+                    .unwrap_or(0.0);
+                json_line(record, timestamp)
+            }
+        };
+        self.reporter.suspend(|| eprintln!("{}", line));
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_log_lines() {
+        let args = format_args!("running steghide");
+        let record = Record::builder()
+            .level(Level::Debug)
+            .target("stegfile::steghide")
+            .args(args)
+            .build();
+        assert_eq!(text_line(&record), "debug: running steghide");
+        assert_eq!(
+            json_line(&record, 1.5),
+            r#"{"level":"debug","message":"running steghide","target":"stegfile::steghide","timestamp":1.5}"#
+        );
+
+        let args = format_args!("could not save capacity cache");
+        let record = Record::builder().level(Level::Warn).args(args).build();
+        assert_eq!(text_line(&record), "warning: could not save capacity cache");
+    }
+}
@@ -1,89 +1,257 @@
-// Lookup custom library
-mod steglib;
-use std::fs::File;
-use std::io::Read;
-use steglib::capacity::{MulCapacity, MulFullCapacity, MulScrambledCapacity};
-use steglib::cli::{Cli, Commands, SplitModeEnum};
-use steglib::embed::mul_embed;
-use steglib::extract::mul_extract;
-use steglib::split::{SplitChunks, SplitScrambled};
-use steglib::util::find_jpg_images;
+mod cli;
+mod logger;
+mod progress;
+
+use cli::{Cli, Commands, OutputFormatEnum, PlacementEnum, SelectionArgs};
+use logger::Logger;
+use progress::ProgressReporter;
+use stegfile::capacity::capacity_report;
+use stegfile::discover::find_jpg_images;
+use stegfile::embed::{EmbedOptions, OutputDir};
+use stegfile::events::{Observer, Silent};
+use stegfile::extract::ExtractOptions;
+use stegfile::placement::Placement;
+use stegfile::split::{SplitChunks, SplitScrambled};
+
+use stegfile::Error;
 
 use clap::Parser;
-use std::path::Path;
+use log::error;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/**
+ * File name standing for stdin or stdout.
+ */
+const STDIO: &str = "-";
+
+/**
+ * How and where the result of a command is printed. Results go to stdout, unless the payload is
+ * being written there.
+ */
+#[derive(Clone, Copy)]
+struct ResultPrinter {
+    format: OutputFormatEnum,
+    to_stderr: bool,
+}
+
+impl ResultPrinter {
+    /**
+     * Print `report` as one line of JSON, if that is the format results are printed in.
+     */
+    fn report(&self, report: &impl Serialize) {
+        if self.format != OutputFormatEnum::Json {
+            return;
+        }
+
+        let line = serde_json::to_string(report).expect("reports can always be serialized");
+        match self.to_stderr {
+            true => eprintln!("{}", line),
+            false => println!("{}", line),
+        }
+    }
+
+    /**
+     * Log `message`, report `error` and exit.
+     */
+    fn exit_with(&self, message: String, error: &Error) -> ! {
+        error!("{}", message);
+        let mut details = serde_json::to_value(error).expect("errors can always be serialized");
+        details["message"] = error.to_string().into();
+        self.report(&serde_json::json!({ "error": details }));
+        std::process::exit(1);
+    }
+
+    /**
+     * Unwrap `result`, or report its error and exit.
+     */
+    fn exit_on_error<T>(&self, result: stegfile::Result<T>) -> T {
+        result
+            .unwrap_or_else(|error| self.exit_with(format!("{}. Please try again.", error), &error))
+    }
+}
+
+/**
+ * Find the images in `image_dir` chosen by `selection`, exiting if they can't be searched for.
+ */
+fn discover_images(
+    printer: ResultPrinter,
+    image_dir: &str,
+    selection: &SelectionArgs,
+    observer: &dyn Observer,
+) -> Vec<String> {
+    printer.exit_on_error(find_jpg_images(
+        Path::new(image_dir),
+        &selection.to_filter(),
+        observer,
+    ))
+}
+
+/**
+ * Read the payload from `input_file`, or from stdin if it is `-`.
+ */
+fn read_payload(input_file: &str) -> stegfile::Result<Vec<u8>> {
+    if input_file != STDIO {
+        return fs::read(input_file).map_err(|e| Error::io(Path::new(input_file), e));
+    }
+
+    let mut payload = Vec::new();
+    io::stdin()
+        .lock()
+        .read_to_end(&mut payload)
+        .map_err(|e| Error::io(Path::new("stdin"), e))?;
+    Ok(payload)
+}
+
+/**
+ * Write `payload` to stdout.
+ */
+fn write_payload_to_stdout(payload: &[u8]) -> stegfile::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(payload)
+        .and_then(|()| stdout.flush())
+        .map_err(|e| Error::io(Path::new("stdout"), e))
+}
 
 fn main() {
     let cli = Cli::parse();
+    let reporter = Arc::new(ProgressReporter::new());
+    Logger::init(cli.log_level(), cli.log_format, reporter.clone())
+        .expect("no other logger is installed");
+
+    // Quiet runs show no progress, so nothing needs to observe them
+    let observer: Arc<dyn Observer> = match cli.quiet {
+        true => Arc::new(Silent),
+        false => reporter,
+    };
+
+    // Extracting to stdout leaves stderr for the report
+    let printer = ResultPrinter {
+        format: cli.format,
+        to_stderr: matches!(&cli.command, Commands::Extract { output_file, .. } if output_file == STDIO),
+    };
 
     match &cli.command {
         Commands::Extract {
             image_dir,
             passphrase,
             output_file,
+            selection,
         } => {
-            let mut images: Vec<String> = Vec::new();
-            let image_path = Path::new(image_dir);
-            find_jpg_images(image_path, &mut images);
+            let images = discover_images(printer, image_dir, selection, observer.as_ref());
 
-            if !image_path.is_dir() {
-                println!("{} is not a directory. Please try again.", image_dir);
-                std::process::exit(1);
-            }
-            println!("Found {} JPG images.", images.len());
+            let options = ExtractOptions::new(passphrase)
+                .observer(observer.clone())
+                .split_mode(cli.split_mode.to_split_mode())
+                .jobs(cli.jobs);
+            let result = match output_file.as_str() {
+                STDIO => options.extract(&images).and_then(|(payload, report)| {
+                    write_payload_to_stdout(&payload)?;
+                    Ok(report)
+                }),
+                _ => options.extract_to(&images, Path::new(output_file)),
+            };
 
-            match &cli.split_mode {
-                SplitModeEnum::Scrambled => {
-                    mul_extract::<SplitScrambled>(&images, passphrase, output_file);
-                }
-                SplitModeEnum::Full => {
-                    mul_extract::<SplitChunks>(&images, passphrase, output_file);
-                }
+            match result {
+                Ok(report) => printer.report(&report),
+                Err(error) => printer.exit_with(format!("extraction failed: {}", error), &error),
             }
         }
         Commands::Embed {
             image_dir,
             passphrase,
             input_file,
+            output_dir,
+            copy_unused,
+            resume,
+            discard,
+            selection,
+            placement,
+            max_rate,
+            chaff,
+            backend,
         } => {
-            let mut images: Vec<String> = Vec::new();
-            let image_path = Path::new(image_dir);
-            find_jpg_images(image_path, &mut images);
+            let images = discover_images(printer, image_dir, selection, observer.as_ref());
+            let image_root = printer.exit_on_error(
+                Path::new(image_dir)
+                    .canonicalize()
+                    .map_err(|e| Error::io(Path::new(image_dir), e)),
+            );
+
+            let buffer = printer.exit_on_error(read_payload(input_file));
 
-            if !image_path.is_dir() {
-                println!("{} is not a directory. Please try again.", image_dir);
-                std::process::exit(1);
+            let placement = match placement {
+                PlacementEnum::All => Placement::All,
+                PlacementEnum::Minimal => Placement::Minimal,
+                PlacementEnum::Spread => Placement::Spread {
+                    max_rate: *max_rate,
+                },
+            };
+
+            let mut options = EmbedOptions::new(passphrase)
+                .observer(observer.clone())
+                .split_mode(cli.split_mode.to_split_mode())
+                .placement(placement)
+                .chaff(*chaff)
+                .resume(*resume)
+                .discard(*discard)
+                .capacity_backend(backend.to_backend())
+                .jobs(cli.jobs);
+            if let Some(output_dir) = output_dir {
+                options = options.output_dir(OutputDir {
+                    image_dir: image_root.clone(),
+                    output_dir: PathBuf::from(output_dir),
+                    copy_unused: *copy_unused,
+                });
             }
 
-            let mut file = File::open(input_file).unwrap();
-            let mut buffer: Vec<u8> = Vec::new();
-            let _ = file.read_to_end(&mut buffer);
+            let result = options.embed(buffer, &images);
 
-            match &cli.split_mode {
-                SplitModeEnum::Scrambled => {
-                    mul_embed::<SplitScrambled>(buffer, &images, passphrase);
-                }
-                SplitModeEnum::Full => {
-                    mul_embed::<SplitChunks>(buffer, &images, passphrase);
-                }
+            match result {
+                Ok(report) => printer.report(&report),
+                Err(error) => printer.exit_with(format!("embedding failed: {}", error), &error),
             }
         }
 
-        Commands::Capacity { image_dir } => {
-            let mut images: Vec<String> = Vec::new();
-            let image_path = Path::new(image_dir);
-            if !image_path.is_dir() {
-                println!("{} is not a directory. Please try again.", image_dir);
-                std::process::exit(1);
-            }
-            println!("Searching scrambled images...");
-            find_jpg_images(image_path, &mut images);
-            println!("Done.");
+        Commands::Capacity {
+            image_dir,
+            selection,
+            max_rate,
+            backend,
+        } => {
+            let images = discover_images(printer, image_dir, selection, observer.as_ref());
 
-            let scrambled_capacity = MulScrambledCapacity::capacity(&images);
-            let full_capacity = MulFullCapacity::capacity(&images);
+            let result = capacity_report(
+                &images,
+                *max_rate,
+                backend.to_backend(),
+                cli.jobs,
+                observer.as_ref(),
+            );
+            let report = match result {
+                Ok(report) => report,
+                Err(error) => {
+                    printer.exit_with(format!("finding capacity failed: {}", error), &error)
+                }
+            };
 
-            println!("Capacity using scrambled egg: {}", scrambled_capacity);
-            println!("Capacity using whole egg: {}", full_capacity);
+            match cli.format {
+                OutputFormatEnum::Text => {
+                    println!(
+                        "Capacity using scrambled egg: {}",
+                        report.total::<SplitScrambled>()
+                    );
+                    println!(
+                        "Capacity using whole egg: {}",
+                        report.total::<SplitChunks>()
+                    );
+                }
+                OutputFormatEnum::Json => printer.report(&report),
+            }
         }
     }
 }
@@ -0,0 +1,255 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/**
+ * Version of the piece layout below. Pieces written before headers were sealed start with their
+ * index as a big endian u64, so their first byte reads as version 0.
+ */
+const VERSION: u8 = 1;
+const VERSION_SIZE: usize = 1;
+const NONCE_SIZE: usize = 12;
+const SET_ID_SIZE: usize = 16;
+const DIGEST_SIZE: usize = 32;
+const FIELDS_SIZE: usize = 8 + 8 + 8 + SET_ID_SIZE + DIGEST_SIZE;
+const TAG_SIZE: usize = 16;
+
+/**
+ * Every piece starts with a sealed header: the format version, a random nonce, then the header
+ * fields encrypted and authenticated with ChaCha20-Poly1305, which authenticates the version as
+ * well. The fields are the piece index, the total number of
+ * pieces in the set and the number of payload bytes in the piece, all as big endian u64, the id
+ * of the set and a SHA-256 digest of the payload bytes. Anything in the piece past those payload
+ * bytes is chaff.
+ */
+pub const HEADER_SIZE: usize = VERSION_SIZE + NONCE_SIZE + FIELDS_SIZE + TAG_SIZE;
+
+/**
+ * Salt for deriving the header key. It is fixed because the key has to be derived again from the
+ * passphrase alone before any piece can be read.
+ */
+const KEY_SALT: &[u8] = b"stegfile piece header key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceHeader {
+    pub index: u64,
+    pub count: u64,
+    pub length: u64,
+    /// Identifies the embed a piece belongs to, so that pieces of different sets hidden with the
+    /// same passphrase are never mixed up.
+    pub set_id: [u8; SET_ID_SIZE],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceError {
+    /// The piece is too short to hold a header, or the payload its header declares.
+    Truncated,
+    /// The header was not sealed with this key, or the piece has been modified.
+    Tampered,
+    /// The piece is laid out in a format version other than this one.
+    UnsupportedVersion(u8),
+}
+
+/**
+ * Key that piece headers are sealed with, derived from the passphrase.
+ */
+#[derive(Clone)]
+pub struct PieceKey {
+    cipher: ChaCha20Poly1305,
+}
+
+impl PieceKey {
+    pub fn derive(passphrase: &str) -> PieceKey {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), KEY_SALT, &mut key)
+            .expect("Argon2 accepts any passphrase with a fixed salt of this length");
+
+        PieceKey {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        }
+    }
+}
+
+/**
+ * Id for the set of pieces holding `payload`. Resuming an embed of the same payload gives the
+ * same id.
+ */
+pub fn set_id_for(payload: &[u8]) -> [u8; SET_ID_SIZE] {
+    let digest = Sha256::digest(payload);
+    digest[..SET_ID_SIZE].try_into().unwrap()
+}
+
+/**
+ * Build the piece described by `header`, holding `data`. If `pad_to` is larger than `data`, the
+ * piece is padded with random chaff so that it holds `pad_to` bytes after the header.
+ */
+pub fn encode_piece(key: &PieceKey, header: PieceHeader, data: &[u8], pad_to: usize) -> Vec<u8> {
+    debug_assert_eq!(header.length, data.len() as u64);
+
+    let mut fields = Vec::with_capacity(FIELDS_SIZE);
+    fields.extend(header.index.to_be_bytes());
+    fields.extend(header.count.to_be_bytes());
+    fields.extend(header.length.to_be_bytes());
+    fields.extend(header.set_id);
+    fields.extend(Sha256::digest(data));
+
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = key
+        .cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &fields,
+                aad: &[VERSION],
+            },
+        )
+        .expect("Encrypting a short buffer cannot fail");
+
+    let mut piece = Vec::with_capacity(HEADER_SIZE + data.len().max(pad_to));
+    piece.push(VERSION);
+    piece.extend(nonce);
+    piece.extend(sealed);
+    piece.extend(data);
+
+    if pad_to > data.len() {
+        let mut chaff = vec![0u8; pad_to - data.len()];
+        rand::thread_rng().fill_bytes(&mut chaff);
+        piece.extend(chaff);
+    }
+
+    piece
+}
+
+/**
+ * Open the header of `piece` and return it with the payload bytes, dropping any chaff.
+ */
+pub fn decode_piece<'a>(
+    key: &PieceKey,
+    piece: &'a [u8],
+) -> Result<(PieceHeader, &'a [u8]), PieceError> {
+    match piece.first() {
+        Some(&VERSION) => {}
+        Some(&version) => return Err(PieceError::UnsupportedVersion(version)),
+        None => return Err(PieceError::Truncated),
+    }
+    if piece.len() < HEADER_SIZE {
+        return Err(PieceError::Truncated);
+    }
+
+    let nonce = Nonce::from_slice(&piece[VERSION_SIZE..VERSION_SIZE + NONCE_SIZE]);
+    let sealed = Payload {
+        msg: &piece[VERSION_SIZE + NONCE_SIZE..HEADER_SIZE],
+        aad: &piece[..VERSION_SIZE],
+    };
+    let fields = key
+        .cipher
+        .decrypt(nonce, sealed)
+        .map_err(|_| PieceError::Tampered)?;
+
+    let header = PieceHeader {
+        index: u64::from_be_bytes(fields[0..8].try_into().unwrap()),
+        count: u64::from_be_bytes(fields[8..16].try_into().unwrap()),
+        length: u64::from_be_bytes(fields[16..24].try_into().unwrap()),
+        set_id: fields[24..40].try_into().unwrap(),
+    };
+    let digest = &fields[40..FIELDS_SIZE];
+
+    let end = usize::try_from(header.length)
+        .ok()
+        .and_then(|length| HEADER_SIZE.checked_add(length))
+        .ok_or(PieceError::Truncated)?;
+    let data = piece.get(HEADER_SIZE..end).ok_or(PieceError::Truncated)?;
+
+    if Sha256::digest(data).as_slice() != digest {
+        return Err(PieceError::Tampered);
+    }
+
+    Ok((header, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(index: u64, count: u64, length: u64) -> PieceHeader {
+        PieceHeader {
+            index,
+            count,
+            length,
+            set_id: set_id_for(b"payload"),
+        }
+    }
+
+    #[test]
+    fn test_piece_round_trip() {
+        let key = PieceKey::derive("secret");
+
+        let piece = encode_piece(&key, header(1, 4, 3), &[7, 8, 9], 0);
+        assert_eq!(piece.len(), HEADER_SIZE + 3);
+        assert_eq!(
+            decode_piece(&key, &piece),
+            Ok((header(1, 4, 3), &[7, 8, 9][..]))
+        );
+
+        // Chaff pads every piece to the same size but is dropped when decoding
+        let padded = encode_piece(&key, header(1, 4, 3), &[7, 8, 9], 100);
+        assert_eq!(padded.len(), HEADER_SIZE + 100);
+        assert_eq!(decode_piece(&key, &padded).unwrap().1, &[7, 8, 9][..]);
+
+        // Declares more data than the piece holds
+        assert_eq!(
+            decode_piece(&key, &piece[..HEADER_SIZE + 2]),
+            Err(PieceError::Truncated)
+        );
+        assert_eq!(
+            decode_piece(&key, &piece[..HEADER_SIZE - 1]),
+            Err(PieceError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_piece_rejects_tampering() {
+        let key = PieceKey::derive("secret");
+        let piece = encode_piece(&key, header(0, 2, 3), &[7, 8, 9], 0);
+
+        // The header reveals nothing in plain text
+        assert!(!piece.windows(8).any(|w| w == 2u64.to_be_bytes()));
+
+        assert_eq!(
+            decode_piece(&PieceKey::derive("other"), &piece),
+            Err(PieceError::Tampered)
+        );
+
+        for position in [VERSION_SIZE, NONCE_SIZE + 3, HEADER_SIZE + 1] {
+            let mut tampered = piece.clone();
+            tampered[position] ^= 1;
+            assert_eq!(decode_piece(&key, &tampered), Err(PieceError::Tampered));
+        }
+    }
+
+    #[test]
+    fn test_piece_version() {
+        let key = PieceKey::derive("secret");
+        let piece = encode_piece(&key, header(0, 2, 3), &[7, 8, 9], 0);
+        assert_eq!(HEADER_SIZE, 101);
+        assert_eq!(piece[0], VERSION);
+
+        // Pieces from before headers were sealed start with their index as a big endian u64
+        let mut unversioned = 3u64.to_be_bytes().to_vec();
+        unversioned.extend([7, 8, 9]);
+        assert_eq!(
+            decode_piece(&key, &unversioned),
+            Err(PieceError::UnsupportedVersion(0))
+        );
+
+        let mut newer = piece.clone();
+        newer[0] = VERSION + 1;
+        assert_eq!(
+            decode_piece(&key, &newer),
+            Err(PieceError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+}
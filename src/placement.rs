@@ -0,0 +1,252 @@
+use crate::split::Split;
+
+/**
+ * How the images that receive a piece of the payload are chosen.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// Every image receives a piece, in the order the images are given. Images that are not
+    /// needed for the payload receive an empty piece.
+    All,
+
+    /// Only the fewest images needed receive a piece, preferring the ones with the most capacity.
+    /// Every other image is left untouched.
+    Minimal,
+
+    /// No image is filled past `max_rate` percent of its capacity. The payload is spread as
+    /// evenly as possible over as many images as that takes, preferring the ones with the most
+    /// capacity. Every other image is left untouched.
+    Spread { max_rate: u8 },
+}
+
+/**
+ * Part of `capacity` that may be used when embedding at no more than `max_rate` percent.
+ */
+pub fn capacity_at_rate(capacity: u64, max_rate: u8) -> u64 {
+    (capacity as u128 * max_rate.min(100) as u128 / 100) as u64
+}
+
+/**
+ * Indices of `capacities`, largest capacity first. Images with the same capacity keep their
+ * order.
+ */
+fn by_capacity(capacities: &[u64]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..capacities.len()).collect();
+    indices.sort_by_key(|&i| std::cmp::Reverse(capacities[i]));
+    indices
+}
+
+/**
+ * `capacities`, all cut down to the smallest of them with `chaff`, as every carrier then holds
+ * the same amount of data.
+ */
+fn equalized(capacities: &[u64], chaff: bool) -> Vec<u64> {
+    let smallest = capacities.iter().copied().min().unwrap_or(0);
+    match chaff {
+        true => vec![smallest; capacities.len()],
+        false => capacities.to_vec(),
+    }
+}
+
+/**
+ * Shortest prefix of `order` whose capacities, equalized with `chaff`, fit `payload_size` bytes
+ * split with `T`.
+ */
+fn fewest_carriers<T: Split>(
+    order: &[usize],
+    payload_size: u64,
+    capacities: &[u64],
+    chaff: bool,
+) -> Option<Vec<usize>> {
+    let mut chosen_capacities: Vec<u64> = Vec::new();
+    for (count, &i) in order.iter().enumerate() {
+        chosen_capacities.push(capacities[i]);
+        if T::max_payload(&equalized(&chosen_capacities, chaff)) >= payload_size {
+            return Some(order[..=count].to_vec());
+        }
+    }
+
+    None
+}
+
+/**
+ * Lowest fill level at which `capacities`, each capped at the level, still fit `payload_size`
+ * bytes split with `T`. `capacities` must fit the payload uncapped.
+ */
+fn even_level<T: Split>(payload_size: u64, capacities: &[u64]) -> u64 {
+    let fits = |level: u64| {
+        let capped: Vec<u64> = capacities.iter().map(|&c| c.min(level)).collect();
+        T::max_payload(&capped) >= payload_size
+    };
+
+    let mut low = 0;
+    let mut high = capacities.iter().copied().max().unwrap_or(0);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if fits(middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    high
+}
+
+/**
+ * Choose which images receive a piece of a `payload_size` byte payload split with `T`, given the
+ * capacity of each image. Returns the index into `capacities` of each chosen image, in piece
+ * order, along with how many bytes of the payload it may be given. With `chaff` every chosen
+ * image is given the same number of bytes, and the images are chosen so that the payload still
+ * fits.
+ *
+ * If the payload does not fit whichever images are chosen, every image is returned so that the
+ * capacity check reports how far off it is.
+ */
+pub fn select_carriers<T: Split>(
+    placement: Placement,
+    payload_size: u64,
+    capacities: &[u64],
+    chaff: bool,
+) -> Vec<(usize, u64)> {
+    let (chosen, limits): (Vec<usize>, Vec<u64>) = match placement {
+        Placement::All => ((0..capacities.len()).collect(), capacities.to_vec()),
+        Placement::Minimal => {
+            let order = by_capacity(capacities);
+            match fewest_carriers::<T>(&order, payload_size, capacities, chaff) {
+                Some(chosen) => (chosen, capacities.to_vec()),
+                None => ((0..capacities.len()).collect(), capacities.to_vec()),
+            }
+        }
+        Placement::Spread { max_rate } => {
+            let limits: Vec<u64> = capacities
+                .iter()
+                .map(|&c| capacity_at_rate(c, max_rate))
+                .collect();
+
+            let order = by_capacity(&limits);
+            let chosen = match fewest_carriers::<T>(&order, payload_size, &limits, chaff) {
+                Some(chosen) => chosen,
+                None => (0..limits.len()).collect(),
+            };
+
+            let chosen_limits: Vec<u64> = chosen.iter().map(|&i| limits[i]).collect();
+            let chosen_limits = equalized(&chosen_limits, chaff);
+            if T::max_payload(&chosen_limits) < payload_size {
+                (chosen, limits)
+            } else {
+                let level = even_level::<T>(payload_size, &chosen_limits);
+                let capped = limits.iter().map(|&limit| limit.min(level)).collect();
+                (chosen, capped)
+            }
+        }
+    };
+
+    let chosen_capacities: Vec<u64> = chosen.iter().map(|&i| limits[i]).collect();
+    chosen
+        .into_iter()
+        .zip(equalized(&chosen_capacities, chaff))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::split::{SplitChunks, SplitScrambled};
+
+    #[test]
+    fn test_select_all() {
+        let capacities: Vec<u64> = vec![10, 40, 20];
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::All, 5, &capacities, false),
+            vec!((0, 10), (1, 40), (2, 20))
+        );
+    }
+
+    #[test]
+    fn test_select_minimal() {
+        let capacities: Vec<u64> = vec![10, 40, 20, 40];
+
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 0, &capacities, false),
+            vec!((1, 40))
+        );
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 40, &capacities, false),
+            vec!((1, 40))
+        );
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 81, &capacities, false),
+            vec!((1, 40), (3, 40), (2, 20))
+        );
+        assert_eq!(
+            select_carriers::<SplitScrambled>(Placement::Minimal, 60, &capacities, false),
+            vec!((1, 40), (3, 40))
+        );
+
+        // Too big for any choice of images
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 200, &capacities, false),
+            vec!((0, 10), (1, 40), (2, 20), (3, 40))
+        );
+    }
+
+    #[test]
+    fn test_select_spread() {
+        let capacities: Vec<u64> = vec![100, 400, 200, 400];
+        let spread = Placement::Spread { max_rate: 50 };
+
+        // 200 + 200 at 50% fits 300 bytes, spread evenly as 150 each
+        assert_eq!(
+            select_carriers::<SplitChunks>(spread, 300, &capacities, false),
+            vec!((1, 150), (3, 150))
+        );
+        // 200 + 200 + 100 at 50%; the smallest image is full so the others take the rest
+        assert_eq!(
+            select_carriers::<SplitChunks>(spread, 480, &capacities, false),
+            vec!((1, 190), (3, 190), (2, 100))
+        );
+        assert_eq!(
+            select_carriers::<SplitScrambled>(spread, 300, &capacities, false),
+            vec!((1, 150), (3, 150))
+        );
+
+        // Too big at 50%, so the capacity check sees every image at 50%
+        assert_eq!(
+            select_carriers::<SplitChunks>(spread, 600, &capacities, false),
+            vec!((0, 50), (1, 200), (2, 100), (3, 200))
+        );
+        assert_eq!(capacity_at_rate(999, 20), 199);
+    }
+
+    #[test]
+    fn test_select_with_chaff() {
+        let capacities: Vec<u64> = vec![100, 20, 20, 20, 20, 20];
+
+        // [100, 20] would only hold 2 x 20 once equalized, so every image is needed
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 110, &capacities, true),
+            vec!((0, 20), (1, 20), (2, 20), (3, 20), (4, 20), (5, 20))
+        );
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::Minimal, 100, &capacities, true),
+            vec!((0, 100))
+        );
+        assert_eq!(
+            select_carriers::<SplitChunks>(Placement::All, 10, &capacities, true),
+            vec!((0, 20), (1, 20), (2, 20), (3, 20), (4, 20), (5, 20))
+        );
+
+        // At 50% the images hold [50, 10, 10, 10, 10, 10]; 55 bytes need all six at 10 each
+        let spread = Placement::Spread { max_rate: 50 };
+        assert_eq!(
+            select_carriers::<SplitChunks>(spread, 55, &capacities, true),
+            vec!((0, 10), (1, 10), (2, 10), (3, 10), (4, 10), (5, 10))
+        );
+        // 40 bytes fit the largest image alone, spread as evenly as that allows
+        assert_eq!(
+            select_carriers::<SplitChunks>(spread, 40, &capacities, true),
+            vec!((0, 40))
+        );
+    }
+}
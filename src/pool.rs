@@ -0,0 +1,137 @@
+use crate::error::{Error, Result};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/**
+ * How many images are worked on at once unless told otherwise: one per CPU.
+ */
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |cpus| cpus.get())
+}
+
+/**
+ * Call `f` on every item of `items` using at most `workers` threads. Results are returned in the
+ * order of `items`, regardless of the order they finish in.
+ */
+pub fn parallel_map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is handled by a worker"))
+        .collect()
+}
+
+/**
+ * Call `f` on every item of `items` using at most `workers` threads, like `parallel_map`, but stop
+ * starting new items once any item has failed. Items that were never started because of that are
+ * `None`; items that were already running are left to finish.
+ */
+pub fn try_parallel_map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<Option<Result<R>>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<R> + Sync,
+{
+    let cancelled = AtomicBool::new(false);
+    parallel_map(items, workers, |item| {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let result = f(item);
+        if result.is_err() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        Some(result)
+    })
+}
+
+/**
+ * Turn per item results into the values of every item, or every error if any item failed.
+ */
+pub fn collect_results<T>(results: Vec<Result<T>>) -> Result<Vec<T>> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(values),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Failures { errors }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..50).collect();
+        let doubled = parallel_map(&items, 4, |&i| {
+            // Later items finish first
+            thread::sleep(Duration::from_millis(50 - i));
+            i * 2
+        });
+        assert_eq!(doubled, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u64], 4, |&i| i).is_empty());
+    }
+
+    #[test]
+    fn test_try_parallel_map_cancels_after_failure() {
+        let items: Vec<u64> = (0..20).collect();
+        let results = try_parallel_map(&items, 1, |&i| match i {
+            3 => Err(Error::NoImages),
+            _ => Ok(i),
+        });
+
+        assert_eq!(results[2], Some(Ok(2)));
+        assert_eq!(results[3], Some(Err(Error::NoImages)));
+        assert!(results[4..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_collect_results() {
+        assert_eq!(collect_results(vec![Ok(1), Ok(2)]), Ok(vec!(1, 2)));
+        assert_eq!(
+            collect_results(vec![Ok(1), Err(Error::NoImages)]),
+            Err(Error::NoImages)
+        );
+        assert_eq!(
+            collect_results::<u64>(vec![Err(Error::NoImages), Err(Error::NoPieces)]),
+            Err(Error::Failures {
+                errors: vec!(Error::NoImages, Error::NoPieces)
+            })
+        );
+    }
+}
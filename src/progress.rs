@@ -0,0 +1,116 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use std::sync::Mutex;
+use stegfile::events::{Event, Observer};
+
+const COUNT_TEMPLATE: &str = "{msg} [{bar:40}] {pos}/{len} images ({eta})";
+const BYTES_TEMPLATE: &str = "{msg} [{bar:40}] {bytes}/{total_bytes} ({eta})";
+
+/**
+ * Shows the events of an operation as a progress bar with an ETA for each of its phases. Anything
+ * worth keeping is logged, which prints it above the bar.
+ */
+pub struct ProgressReporter {
+    bar: Mutex<ProgressBar>,
+}
+
+impl ProgressReporter {
+    pub fn new() -> ProgressReporter {
+        ProgressReporter {
+            bar: Mutex::new(ProgressBar::hidden()),
+        }
+    }
+
+    /**
+     * Replace the current bar with a new one counting to `length` with `template`.
+     */
+    fn start(&self, length: u64, template: &str, message: &'static str) {
+        let bar = ProgressBar::new(length).with_message(message).with_style(
+            ProgressStyle::with_template(template)
+                .expect("the progress templates are valid")
+                .progress_chars("=> "),
+        );
+
+        let mut current = self.bar.lock().unwrap();
+        current.finish_and_clear();
+        *current = bar;
+    }
+
+    /**
+     * Hide the bar while `f` writes to the terminal.
+     */
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.lock().unwrap().suspend(f)
+    }
+
+    fn advance(&self, by: u64) {
+        self.bar.lock().unwrap().inc(by);
+    }
+
+    fn set_message(&self, message: &'static str) {
+        self.bar.lock().unwrap().set_message(message);
+    }
+
+    fn finish(&self) {
+        self.bar.lock().unwrap().finish_and_clear();
+    }
+}
+
+impl Observer for ProgressReporter {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::ImageFound { .. } => {}
+            Event::DiscoveryFinished { images } => info!("found {} images", images),
+
+            Event::CapacityStarted { images } => {
+                self.start(*images as u64, COUNT_TEMPLATE, "Finding capacities")
+            }
+            Event::CapacityFound { .. } => self.advance(1),
+            Event::CapacityFailed { image, error } => {
+                warn!("could not find the capacity of {}: {}", image, error);
+                self.advance(1);
+            }
+            Event::CapacityFinished => self.finish(),
+
+            Event::Split { pieces, bytes } => {
+                debug!("split {} bytes into {} pieces", bytes, pieces)
+            }
+            Event::EmbedStarted { bytes, .. } => self.start(*bytes, BYTES_TEMPLATE, "Embedding"),
+            Event::PieceStarted { .. } => {}
+            Event::PieceEmbedded { bytes, .. } => self.advance(*bytes),
+            Event::PieceSkipped {
+                image,
+                index,
+                bytes,
+            } => {
+                info!("piece {} is already embedded into {}", index, image);
+                self.advance(*bytes);
+            }
+            Event::PieceFailed { image, error, .. } => {
+                warn!("could not embed into {}: {}", image, error)
+            }
+            Event::Verifying { .. } => self.set_message("Verifying"),
+            Event::Committing { .. } => self.set_message("Replacing images"),
+            Event::CopiedUnused { image } => info!("copied unused image {}", image),
+            Event::EmbedFinished => {
+                self.finish();
+                info!("done");
+            }
+
+            Event::ExtractStarted { images } => {
+                self.start(*images as u64, COUNT_TEMPLATE, "Extracting")
+            }
+            Event::Extracted { .. } | Event::NothingHidden { .. } => self.advance(1),
+            Event::ExtractFailed { image, error } => {
+                warn!("could not extract from {}: {}", image, error);
+                self.advance(1);
+            }
+            Event::ExtractFinished { bytes } => {
+                self.finish();
+                debug!("extracted {} bytes", bytes);
+            }
+
+            Event::Warning { message } => warn!("{}", message),
+        }
+    }
+}
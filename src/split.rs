@@ -1,13 +1,42 @@
 use std::cmp::min;
 
-#[allow(dead_code)]
+/**
+ * Split method chosen at runtime, for when it is not known at compile time which `Split` to use.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitMode {
+    /// `SplitScrambled`
+    Scrambled,
+    /// `SplitChunks`
+    #[default]
+    Full,
+}
+
+/**
+ * A way of splitting a payload into one bin per image, and of putting it back together.
+ */
 pub trait Split {
     /**
-     * Split Vec<u8> into Vec<Vec<u8>>, where each vec is filled to less than to equal to the
+     * Name of the split method, as accepted by `--split-mode`.
+     */
+    const NAME: &'static str;
+
+    /**
+     * Split `Vec<u8>` into `Vec<Vec<u8>>`, where each vec is filled to less than to equal to the
      * corresponding size in `bin_capacities`. This does not modify `data`. Any remaining data that
-     * is not filled will be set to 0.
+     * is not filled will be set to 0. `data` must be no larger than `max_payload(bin_capacities)`.
+     */
+    fn split_to_bins(data: &[u8], bin_capacities: &[u64]) -> Vec<Vec<u8>>;
+
+    /**
+     * Largest amount of data that `split_to_bins` can fit into bins of `bin_capacities`.
+     */
+    fn max_payload(bin_capacities: &[u64]) -> u64;
+
+    /**
+     * How much of `max_payload(bin_capacities)` each bin receives.
      */
-    fn split_to_bins(data: &Vec<u8>, bin_capacities: &Vec<u64>) -> Vec<Vec<u8>>;
+    fn max_bins(bin_capacities: &[u64]) -> Vec<u64>;
 
     /**
      * Undo split_to_bins. Does not modify `data`.
@@ -18,7 +47,7 @@ pub trait Split {
 /**
  * Match length of `bins` with `bin_capacities` by adding empty bins.
  */
-fn inflate_bins(bins: &mut Vec<Vec<u8>>, bin_capacities: &Vec<u64>) {
+fn inflate_bins(bins: &mut Vec<Vec<u8>>, bin_capacities: &[u64]) {
     while bins.len() < bin_capacities.len() {
         bins.push(Vec::new()); // Didn't fill all the files? Just make empty files
     }
@@ -41,8 +70,10 @@ fn inflate_bins(bins: &mut Vec<Vec<u8>>, bin_capacities: &Vec<u64>) {
 pub struct SplitScrambled;
 
 impl Split for SplitScrambled {
-    fn split_to_bins(data: &Vec<u8>, bin_capacities: &Vec<u64>) -> Vec<Vec<u8>> {
-        let cloned_data = data.clone();
+    const NAME: &'static str = "scrambled";
+
+    fn split_to_bins(data: &[u8], bin_capacities: &[u64]) -> Vec<Vec<u8>> {
+        let cloned_data = data.to_vec();
         let mut scrambled_content: Vec<Vec<u8>> = vec![Vec::new(); bin_capacities.len()];
 
         // Scramble data into buckets
@@ -52,25 +83,30 @@ impl Split for SplitScrambled {
             next_bin = (next_bin + 1) % bin_capacities.len();
         }
 
-        inflate_bins(&mut scrambled_content, &bin_capacities);
+        inflate_bins(&mut scrambled_content, bin_capacities);
 
         scrambled_content
     }
 
+    fn max_payload(bin_capacities: &[u64]) -> u64 {
+        let smallest_bin = bin_capacities.iter().copied().min().unwrap_or(0);
+        smallest_bin * bin_capacities.len() as u64
+    }
+
+    fn max_bins(bin_capacities: &[u64]) -> Vec<u64> {
+        let smallest_bin = bin_capacities.iter().copied().min().unwrap_or(0);
+        vec![smallest_bin; bin_capacities.len()]
+    }
+
     fn join_bins(data: &[Vec<u8>]) -> Vec<u8> {
         let total_byte_count: usize = data.iter().map(|v| v.len()).sum();
-        let mut unified_piece: Vec<u8> = Vec::with_capacity(total_byte_count);
-        let mut offset: usize = 0;
+        let mut unified_piece: Vec<u8> = vec![0; total_byte_count];
         let bucket_count = data.len();
 
-        for piece in data {
-            let mut piece_num: usize = 0;
-            for byte in piece.clone() {
-                unified_piece[offset + piece_num * bucket_count] = byte;
-                piece_num += 1;
+        for (offset, piece) in data.iter().enumerate() {
+            for (piece_num, byte) in piece.iter().enumerate() {
+                unified_piece[offset + piece_num * bucket_count] = *byte;
             }
-
-            offset += 1;
         }
 
         unified_piece
@@ -93,12 +129,14 @@ impl Split for SplitScrambled {
 pub struct SplitChunks;
 
 impl Split for SplitChunks {
-    fn split_to_bins(data: &Vec<u8>, bin_capacities: &Vec<u64>) -> Vec<Vec<u8>> {
-        let mut cloned_data = data.clone();
+    const NAME: &'static str = "full";
+
+    fn split_to_bins(data: &[u8], bin_capacities: &[u64]) -> Vec<Vec<u8>> {
+        let mut cloned_data = data.to_vec();
         let mut bins = Vec::with_capacity(bin_capacities.len());
         let mut index = 0;
 
-        while cloned_data.len() > 0 {
+        while !cloned_data.is_empty() {
             // Capacity of the bin to fill
             let capacity = bin_capacities[index];
             index += 1;
@@ -110,10 +148,18 @@ impl Split for SplitChunks {
             bins.push(buffer);
         }
 
-        inflate_bins(&mut bins, &bin_capacities);
+        inflate_bins(&mut bins, bin_capacities);
         bins
     }
 
+    fn max_payload(bin_capacities: &[u64]) -> u64 {
+        bin_capacities.iter().sum()
+    }
+
+    fn max_bins(bin_capacities: &[u64]) -> Vec<u64> {
+        bin_capacities.to_vec()
+    }
+
     fn join_bins(data: &[Vec<u8>]) -> Vec<u8> {
         let total_byte_count: usize = data.iter().map(|v| v.len()).sum();
         let mut unified_piece: Vec<u8> = Vec::with_capacity(total_byte_count);
@@ -182,4 +228,29 @@ mod tests {
 
         assert_eq!(data, vec!(10, 20, 30, 40, 50));
     }
+
+    #[test]
+    fn test_join_round_trip() {
+        let data: Vec<u8> = vec![10, 20, 30, 40, 50];
+        let buckets: Vec<u64> = vec![2, 2, 3];
+
+        let scrambled = SplitScrambled::split_to_bins(&data, &buckets);
+        assert_eq!(SplitScrambled::join_bins(&scrambled), data);
+
+        let chunks = SplitChunks::split_to_bins(&data, &buckets);
+        assert_eq!(SplitChunks::join_bins(&chunks), data);
+    }
+
+    #[test]
+    fn test_max_payload() {
+        let buckets: Vec<u64> = vec![3, 5, 4];
+
+        assert_eq!(SplitScrambled::max_payload(&buckets), 9);
+        assert_eq!(SplitChunks::max_payload(&buckets), 12);
+        assert_eq!(SplitScrambled::max_payload(&[]), 0);
+        assert_eq!(SplitChunks::max_payload(&[]), 0);
+
+        assert_eq!(SplitScrambled::max_bins(&buckets), vec!(3, 3, 3));
+        assert_eq!(SplitChunks::max_bins(&buckets), vec!(3, 5, 4));
+    }
 }
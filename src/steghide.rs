@@ -0,0 +1,178 @@
+use crate::error::{Error, Result};
+use log::debug;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+/**
+ * `command` as it would be typed, with the passphrase left out so that it can be logged.
+ */
+fn describe(command: &Command) -> String {
+    let mut words = vec![command.get_program().to_string_lossy().to_string()];
+    let mut hide_next = false;
+    for arg in command.get_args() {
+        words.push(match hide_next {
+            true => "<passphrase>".to_string(),
+            false => arg.to_string_lossy().to_string(),
+        });
+        hide_next = arg == "-p";
+    }
+    words.join(" ")
+}
+
+fn run_error(image: &str, error: std::io::Error) -> Error {
+    match error.kind() {
+        ErrorKind::NotFound => Error::SteghideMissing,
+        _ => Error::io(Path::new(image), error),
+    }
+}
+
+/**
+ * Log how `output` ended. Stdout may hold piece data, so only its size is logged.
+ */
+fn log_output(output: &Output, image: &str) {
+    debug!(
+        "steghide {} on {}, writing {} bytes to stdout",
+        output.status,
+        image,
+        output.stdout.len()
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        debug!("steghide stderr on {}: {}", image, stderr.trim());
+    }
+}
+
+/**
+ * Run `command`, a steghide invocation working on `image`, and wait for it to finish.
+ */
+pub fn output(command: &mut Command, image: &str) -> Result<Output> {
+    debug!("running {}", describe(command));
+    let output = command.output().map_err(|e| run_error(image, e))?;
+    log_output(&output, image);
+    Ok(output)
+}
+
+/**
+ * Run `command` like `output`, writing `input` to its stdin. Nothing is written to disk on the
+ * way, so `input` can be a piece.
+ */
+pub fn output_with_input(command: &mut Command, image: &str, input: &[u8]) -> Result<Output> {
+    debug!(
+        "running {} with {} bytes on stdin",
+        describe(command),
+        input.len()
+    );
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| run_error(image, e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+
+    // Writing from another thread keeps steghide from blocking on a full stdout or stderr pipe
+    // while stdin is still being written. Stdin is closed once the writer is done.
+    let (output, written) = thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output();
+        (
+            output,
+            writer.join().expect("the stdin writer does not panic"),
+        )
+    });
+    let output = output.map_err(|e| run_error(image, e))?;
+    log_output(&output, image);
+
+    // When steghide gives up early it stops reading, so its own error says more than ours
+    match written {
+        Err(e) if output.status.success() => Err(Error::io(Path::new(image), e)),
+        _ => Ok(output),
+    }
+}
+
+/**
+ * The error steghide reported in `stderr` while working on `image`, if it is one that is
+ * recognised.
+ */
+pub fn classify(image: &str, stderr: &str) -> Option<Error> {
+    let image = image.to_string();
+    if stderr.contains("could not extract any data with that passphrase") {
+        Some(Error::NoData { image })
+    } else if stderr.contains("format of the file") && stderr.contains("not supported") {
+        Some(Error::UnsupportedFormat { image })
+    } else if stderr.contains("too short to embed") {
+        Some(Error::CapacityExceeded { image })
+    } else {
+        None
+    }
+}
+
+/**
+ * Fail unless steghide, working on `image`, exited successfully. Failures that are not recognised
+ * are passed to `otherwise` along with what steghide printed.
+ */
+pub fn check(output: &Output, image: &str, otherwise: impl FnOnce(String) -> Error) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(classify(image, &stderr).unwrap_or_else(|| otherwise(stderr.trim().to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_hides_passphrase() {
+        let mut command = Command::new("steghide");
+        command
+            .arg("extract")
+            .args(["-sf", "a.jpg"])
+            .args(["-p", "secret"])
+            .arg("-f");
+        assert_eq!(
+            describe(&command),
+            "steghide extract -sf a.jpg -p <passphrase> -f"
+        );
+    }
+
+    #[test]
+    fn test_output_with_input() {
+        // Large enough to fill the stdout pipe while stdin is still being written
+        let input: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+        let output = output_with_input(&mut Command::new("cat"), "a.jpg", &input).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, input);
+    }
+
+    #[test]
+    fn test_classify() {
+        let image = || "a.jpg".to_string();
+        assert_eq!(
+            classify(
+                "a.jpg",
+                "steghide: could not extract any data with that passphrase!\n"
+            ),
+            Some(Error::NoData { image: image() })
+        );
+        assert_eq!(
+            classify(
+                "a.jpg",
+                "steghide: the file format of the file \"a.jpg\" is not supported.\n"
+            ),
+            Some(Error::UnsupportedFormat { image: image() })
+        );
+        assert_eq!(
+            classify(
+                "a.jpg",
+                "steghide: the cover file is too short to embed the data.\n"
+            ),
+            Some(Error::CapacityExceeded { image: image() })
+        );
+        assert_eq!(classify("a.jpg", "steghide: something else\n"), None);
+    }
+}
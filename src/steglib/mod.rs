@@ -1,6 +0,0 @@
-pub mod capacity;
-pub mod cli;
-pub mod embed;
-pub mod extract;
-pub mod split;
-pub mod util;
@@ -0,0 +1,346 @@
+use crate::error::{Error, Result};
+use crate::util::{create_parent_dirs, move_file};
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/**
+ * A carrier taking part in an embed. `steghide` writes the stego image to `staged`, which is only
+ * moved over `target` once every carrier in the set has been embedded and verified.
+ */
+pub struct StagedCarrier {
+    pub source: String,
+    pub staged: PathBuf,
+    pub target: PathBuf,
+}
+
+/**
+ * Stages modified copies of a set of carriers so that either all of them are written or none
+ * are. Dropping a `Transaction` without committing it leaves every target untouched; the staged
+ * copies stay in the staging directory so that an interrupted embed can pick them up again.
+ */
+pub struct Transaction {
+    staging_dir: PathBuf,
+    carriers: Vec<StagedCarrier>,
+}
+
+/**
+ * What committing a carrier changed, so that it can be undone: where the original target was
+ * moved to, if there was one, and the topmost directory that had to be created for the target.
+ */
+struct Committed<'a> {
+    carrier: &'a StagedCarrier,
+    backup: Option<PathBuf>,
+    created_dir: Option<PathBuf>,
+}
+
+/**
+ * Fail if more than one of the images in `writes`, given as (image, target) pairs, would be
+ * written to the same target.
+ */
+pub fn check_targets<'a>(
+    writes: impl IntoIterator<Item = (&'a String, &'a PathBuf)>,
+) -> Result<()> {
+    let mut order: Vec<&PathBuf> = Vec::new();
+    let mut by_target: HashMap<&PathBuf, Vec<String>> = HashMap::new();
+    for (image, target) in writes {
+        let images = by_target.entry(target).or_insert_with(|| {
+            order.push(target);
+            Vec::new()
+        });
+        images.push(image.clone());
+    }
+
+    match order.into_iter().find(|target| by_target[target].len() > 1) {
+        Some(target) => Err(Error::TargetCollision {
+            target: target.display().to_string(),
+            images: by_target.remove(target).unwrap_or_default(),
+        }),
+        None => Ok(()),
+    }
+}
+
+const BACKUP_PREFIX: &str = "backup_";
+
+/**
+ * Where committing the carrier at `index` keeps the image it replaces.
+ */
+fn backup_path(staging_dir: &Path, index: usize) -> PathBuf {
+    staging_dir.join(format!("{}{}", BACKUP_PREFIX, index))
+}
+
+impl Transaction {
+    /**
+     * Start a transaction that writes the stego version of `image_paths[i]` to `targets[i]`,
+     * staging them in `staging_dir`. The staging directory should be on the same filesystem as
+     * the targets so that committing is a rename. Fails if two images share a target.
+     */
+    pub fn new(
+        staging_dir: &Path,
+        image_paths: &[String],
+        targets: &[PathBuf],
+    ) -> Result<Transaction> {
+        check_targets(image_paths.iter().zip(targets))?;
+
+        let carriers = image_paths
+            .iter()
+            .zip(targets)
+            .enumerate()
+            .map(|(i, (source, target))| {
+                let extension = target.extension().and_then(|e| e.to_str()).unwrap_or("jpg");
+                StagedCarrier {
+                    source: source.clone(),
+                    staged: staging_dir.join(format!("carrier_{}.{}", i, extension)),
+                    target: target.clone(),
+                }
+            })
+            .collect();
+
+        Ok(Transaction {
+            staging_dir: staging_dir.to_path_buf(),
+            carriers,
+        })
+    }
+
+    pub fn carriers(&self) -> &[StagedCarrier] {
+        &self.carriers
+    }
+
+    /**
+     * Move every staged carrier over its target, creating the directories it goes in. If any move
+     * fails, the targets that were already replaced are restored from their backups and the
+     * directories that were created are removed before the error is returned, along with any
+     * target that could not be restored.
+     */
+    pub fn commit(self) -> Result<()> {
+        let mut committed: Vec<Committed> = Vec::new();
+
+        for (i, carrier) in self.carriers.iter().enumerate() {
+            let backup = backup_path(&self.staging_dir, i);
+            let result = Transaction::commit_one(carrier, &backup);
+
+            match result {
+                Ok(done) => committed.push(done),
+                Err(error) => {
+                    let mut errors = vec![error];
+                    errors.extend(Transaction::rollback(&committed));
+                    return Err(match errors.len() {
+                        1 => errors.remove(0),
+                        _ => Error::Failures { errors },
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Whether a commit from `staging_dir` was interrupted after replacing some of its targets.
+     */
+    pub fn has_backups(staging_dir: &Path) -> Result<bool> {
+        for entry in fs::read_dir(staging_dir).map_err(|e| Error::io(staging_dir, e))? {
+            let entry = entry.map_err(|e| Error::io(staging_dir, e))?;
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(BACKUP_PREFIX)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /**
+     * Put back the images that a commit into `targets` from `staging_dir` replaced before it was
+     * interrupted. Returns whether there were any.
+     */
+    pub fn restore_backups(staging_dir: &Path, targets: &[PathBuf]) -> Result<bool> {
+        let mut restored = false;
+        for (i, target) in targets.iter().enumerate() {
+            let backup = backup_path(staging_dir, i);
+            if backup.exists() {
+                debug!(
+                    "putting {} back from {}",
+                    target.display(),
+                    backup.display()
+                );
+                move_file(&backup, target).map_err(|e| Error::io(target, e))?;
+                restored = true;
+            }
+        }
+        Ok(restored)
+    }
+
+    /**
+     * Replace the target of `carrier`, keeping the original at `backup` if there was one.
+     */
+    fn commit_one<'a>(carrier: &'a StagedCarrier, backup: &Path) -> Result<Committed<'a>> {
+        debug!(
+            "moving {} over {}",
+            carrier.staged.display(),
+            carrier.target.display()
+        );
+        let created_dir =
+            create_parent_dirs(&carrier.target).map_err(|e| Error::io(&carrier.target, e))?;
+        let backup = if carrier.target.exists() {
+            move_file(&carrier.target, backup).map_err(|e| Error::io(&carrier.target, e))?;
+            Some(backup.to_path_buf())
+        } else {
+            None
+        };
+
+        let committed = Committed {
+            carrier,
+            backup,
+            created_dir,
+        };
+        if let Err(e) = move_file(&carrier.staged, &carrier.target) {
+            if let Some(backup) = &committed.backup {
+                let _ = move_file(backup, &carrier.target);
+            }
+            // The target is not there, so undoing the rest only leaves the directories
+            let _ = Transaction::remove_created_dirs(&committed);
+            return Err(Error::io(&carrier.target, e));
+        }
+
+        Ok(committed)
+    }
+
+    /**
+     * Remove the directories that were created for the target of `committed`, which are empty
+     * once it has been undone.
+     */
+    fn remove_created_dirs(committed: &Committed) -> std::io::Result<()> {
+        let created_dir = match &committed.created_dir {
+            Some(created_dir) => created_dir,
+            None => return Ok(()),
+        };
+
+        for dir in committed.carrier.target.ancestors().skip(1) {
+            fs::remove_dir(dir)?;
+            if dir == created_dir {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Restore the targets of `committed`, returning an error for each one that can't be.
+     */
+    fn rollback(committed: &[Committed]) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for done in committed.iter().rev() {
+            let target = &done.carrier.target;
+            let restored = match &done.backup {
+                Some(backup) => move_file(backup, target),
+                None => fs::remove_file(target),
+            }
+            .and_then(|_| Transaction::remove_created_dirs(done));
+
+            if let Err(e) = restored {
+                errors.push(Error::io(target, e));
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn staged_set(dir: &TempDir) -> (Vec<String>, Transaction) {
+        let images: Vec<String> = (0..3)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.jpg", i));
+                fs::write(&path, b"original").unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+        let targets: Vec<PathBuf> = images.iter().map(PathBuf::from).collect();
+        let staging_dir = dir.path().join("staging");
+        fs::create_dir(&staging_dir).unwrap();
+
+        let transaction = Transaction::new(&staging_dir, &images, &targets).unwrap();
+        (images, transaction)
+    }
+
+    #[test]
+    fn test_commit_replaces_all_targets() {
+        let dir = TempDir::new().unwrap();
+        let (images, transaction) = staged_set(&dir);
+        for carrier in transaction.carriers() {
+            fs::write(&carrier.staged, b"stego").unwrap();
+        }
+        transaction.commit().unwrap();
+
+        for image in &images {
+            assert_eq!(fs::read(image).unwrap(), b"stego");
+        }
+    }
+
+    #[test]
+    fn test_failed_commit_restores_originals() {
+        let dir = TempDir::new().unwrap();
+        let (images, transaction) = staged_set(&dir);
+
+        // The last carrier was never staged, so moving it into place fails.
+        for carrier in &transaction.carriers()[..2] {
+            fs::write(&carrier.staged, b"stego").unwrap();
+        }
+        assert!(transaction.commit().is_err());
+
+        for image in &images {
+            assert_eq!(fs::read(image).unwrap(), b"original");
+        }
+    }
+
+    #[test]
+    fn test_commit_creates_target_dirs() {
+        let dir = TempDir::new().unwrap();
+        let images: Vec<String> = vec!["a.jpg".to_string(), "b.jpg".to_string()];
+        let targets = vec![
+            dir.path().join("out/2020/a.jpg"),
+            dir.path().join("out/2021/b.jpg"),
+        ];
+
+        // Only the first carrier was staged, so the directories made for it are removed again
+        let transaction = Transaction::new(dir.path(), &images, &targets).unwrap();
+        fs::write(&transaction.carriers()[0].staged, b"stego").unwrap();
+        assert!(transaction.commit().is_err());
+        assert!(!dir.path().join("out").exists());
+
+        let transaction = Transaction::new(dir.path(), &images, &targets).unwrap();
+        for carrier in transaction.carriers() {
+            fs::write(&carrier.staged, b"stego").unwrap();
+        }
+        transaction.commit().unwrap();
+        assert_eq!(fs::read(&targets[1]).unwrap(), b"stego");
+    }
+
+    #[test]
+    fn test_rejects_shared_targets() {
+        let dir = TempDir::new().unwrap();
+        let images: Vec<String> = ["2020/a.jpg", "b.jpg", "2021/a.jpg"]
+            .iter()
+            .map(|image| image.to_string())
+            .collect();
+        let targets: Vec<PathBuf> = ["out/a.jpg", "out/b.jpg", "out/a.jpg"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        assert_eq!(
+            Transaction::new(dir.path(), &images, &targets).err(),
+            Some(Error::TargetCollision {
+                target: "out/a.jpg".to_string(),
+                images: vec!("2020/a.jpg".to_string(), "2021/a.jpg".to_string())
+            })
+        );
+    }
+}
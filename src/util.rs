@@ -0,0 +1,134 @@
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/**
+ * Create path to `file_path` if it doesn't exist, then write binary data to file.
+*/
+pub fn write_data_to_file(file_path: &Path, data: &[u8]) -> Result<()> {
+    // Create directories if they don't exist
+    if let Some(parent) = file_path.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+    }
+
+    // Write the data to the file, creating it if it doesn't exist
+    fs::write(file_path, data).map_err(|e| Error::io(file_path, e))
+}
+
+/**
+ * Deepest directory containing every path in `paths`.
+ */
+pub fn common_directory(paths: &[PathBuf]) -> PathBuf {
+    let mut common = paths[0].parent().unwrap_or(Path::new("/")).to_path_buf();
+    while !paths.iter().all(|path| path.starts_with(&common)) {
+        if !common.pop() {
+            break;
+        }
+    }
+    common
+}
+
+/**
+ * Create the missing directories leading up to `path`, returning the topmost one that had to be
+ * created, if any.
+ */
+pub fn create_parent_dirs(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => return Ok(None),
+    };
+
+    let topmost = parent
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .last()
+        .map(Path::to_path_buf);
+    fs::create_dir_all(parent)?;
+    Ok(topmost)
+}
+
+/**
+ * Move `from` to `to`, falling back to copying when they are on different filesystems.
+ */
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/**
+ * Reorder `images` by a hash of the passphrase and each image's path relative to `root`. The
+ * resulting order only depends on the passphrase and the layout of the tree under `root`, not on
+ * where the tree is or the order `images` was in, while hiding which image gets which piece.
+ */
+pub fn order_by_passphrase(images: &mut [String], root: &Path, passphrase: &str) {
+    images.sort_by_cached_key(|image| {
+        let path = Path::new(image);
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(Path::new(path.file_name().unwrap_or_default()));
+
+        let mut hasher = Sha256::new();
+        hasher.update(passphrase.as_bytes());
+        hasher.update([0]);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        (hasher.finalize().to_vec(), image.clone())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_data_to_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("a/b/file");
+        write_data_to_file(&path, b"data").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+
+        // A file where a directory should be
+        assert!(write_data_to_file(&path.join("c"), b"data").is_err());
+    }
+
+    #[test]
+    fn test_common_directory() {
+        let paths = vec![
+            PathBuf::from("/photos/2020/a.jpg"),
+            PathBuf::from("/photos/2021/trip/b.jpg"),
+        ];
+        assert_eq!(common_directory(&paths), PathBuf::from("/photos"));
+        assert_eq!(common_directory(&paths[..1]), PathBuf::from("/photos/2020"));
+    }
+
+    #[test]
+    fn test_order_by_passphrase() {
+        let images: Vec<String> = (0..20).map(|i| format!("/photos/{}.jpg", i)).collect();
+
+        let mut forward = images.clone();
+        order_by_passphrase(&mut forward, Path::new("/photos"), "secret");
+        let mut backward: Vec<String> = images.iter().rev().cloned().collect();
+        order_by_passphrase(&mut backward, Path::new("/photos"), "secret");
+        assert_eq!(forward, backward);
+
+        // The same tree somewhere else gives the same order
+        let mut moved: Vec<String> = (0..20).map(|i| format!("/backup/{}.jpg", i)).collect();
+        order_by_passphrase(&mut moved, Path::new("/backup"), "secret");
+        let moved: Vec<String> = moved
+            .iter()
+            .map(|i| i.replace("/backup", "/photos"))
+            .collect();
+        assert_eq!(forward, moved);
+
+        let mut other = images.clone();
+        order_by_passphrase(&mut other, Path::new("/photos"), "other");
+        assert_ne!(forward, other);
+    }
+}